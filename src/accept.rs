@@ -0,0 +1,218 @@
+//! Accepting connections with a deadline.
+
+use crate::{remaining, sys, PollEx, RawHandle};
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Listeners that were switched to non-blocking by this crate and the amount of calls that currently rely on it.
+/// This ensures that concurrent calls do not switch a listener back to blocking while another call still accepts.
+/// An amount of 0 means that switching the listener back to blocking failed.
+static NON_BLOCKING: Mutex<Vec<(RawHandle, usize)>> = Mutex::new(Vec::new());
+
+/// extension Trait for listeners that combines polling and accepting.
+pub trait PollAccept: PollEx {
+    /// The stream type returned by `accept`.
+    type Stream;
+
+    /// The address type returned by `accept`.
+    type Addr;
+
+    /// Calls `accept` of the listener.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    fn accept(&self) -> io::Result<(Self::Stream, Self::Addr)>;
+
+    /// Calls `set_nonblocking` of the listener.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()>;
+
+    /// Calls `set_nonblocking` of an accepted stream.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    fn set_stream_nonblocking(stream: &Self::Stream, nonblocking: bool) -> io::Result<()>;
+
+    /// Accepts a connection, this function never blocks past the timeout.
+    ///
+    /// Returns Ok(None) if no connection could be accepted before the timeout elapsed.
+    /// A timeout of None waits forever.
+    ///
+    /// Unlike calling `poll` and then `accept`, this function cannot block if another thread
+    /// accepts the connection first. This is done by switching the listener to non-blocking for the
+    /// duration of the `accept` call and retrying with the remaining time if `accept` would block.
+    /// Other threads that call `accept` on the same blocking listener at the same time may
+    /// therefore observe a `WouldBlock` error.
+    ///
+    /// The listener is left untouched if it is already non-blocking, concurrent calls of this function
+    /// on the same listener are safe.
+    /// On Windows the non-blocking state cannot be queried, the listener is always blocking after this call.
+    ///
+    /// The non-blocking state belongs to the socket and not to the handle, so it is also visible through
+    /// duplicates of the handle, for example from `try_clone` or in a child process, and blocking `accept`
+    /// calls on those may observe `WouldBlock` too.
+    ///
+    /// If switching the listener back to blocking fails, the accepted connection is still returned.
+    /// The listener then stays non-blocking and the next call of this function retries switching it back,
+    /// it returns the error of that retry before accepting anything.
+    ///
+    /// The returned stream is blocking unless the listener is non-blocking.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    fn accept_timeout(&self, timeout: Option<Duration>) -> io::Result<Option<(Self::Stream, Self::Addr)>> {
+        let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+
+        loop {
+            let remaining = remaining(timeout, deadline);
+
+            if self.poll(remaining)? {
+                match accept_non_blocking(self) {
+                    Ok(connection) => return Ok(Some(connection)),
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                    Err(err) => return Err(err),
+                }
+            }
+
+            if remaining == Some(Duration::ZERO) {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Accepts connections without blocking until `accept` would block and passes each of them to the function.
+    /// Returns the amount of accepted connections, which may be 0.
+    ///
+    /// This is the companion of edge-triggered polling, see `EpollPoller::add_edge_triggered`,
+    /// a single wakeup then processes a whole burst of connections.
+    /// Connections that were aborted by the peer before they were accepted are skipped.
+    ///
+    /// The listener is switched to non-blocking for the duration of this call like in `accept_timeout`.
+    /// The streams are blocking unless the listener is non-blocking.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors,
+    /// connections that were passed to the function before the error occurred are not returned again.
+    ///
+    fn drain_accept<F: FnMut(Self::Stream, Self::Addr)>(&self, mut func: F) -> io::Result<usize> {
+        with_non_blocking(self, |switched| {
+            let mut count = 0;
+            loop {
+                match self.accept() {
+                    Ok((stream, addr)) => {
+                        if switched {
+                            Self::set_stream_nonblocking(&stream, false)?;
+                        }
+
+                        func(stream, addr);
+                        count += 1;
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(count),
+                    Err(err) if matches!(err.kind(), io::ErrorKind::Interrupted | io::ErrorKind::ConnectionAborted) => {}
+                    Err(err) => return Err(err),
+                }
+            }
+        })
+    }
+}
+
+/// Calls `accept` once without blocking and restores the blocking state of the listener afterward.
+pub fn accept_non_blocking<L: PollAccept + ?Sized>(listener: &L) -> io::Result<(L::Stream, L::Addr)> {
+    with_non_blocking(listener, |switched| {
+        let connection = listener.accept()?;
+        if switched {
+            //Some operating systems let the stream inherit the non-blocking state of the listener.
+            L::set_stream_nonblocking(&connection.0, false)?;
+        }
+
+        Ok(connection)
+    })
+}
+
+/// Runs the function while the listener is non-blocking and restores the blocking state of the listener afterward.
+/// The function receives true if the listener was switched to non-blocking by this crate
+/// and false if the user made it non-blocking.
+#[allow(clippy::significant_drop_tightening)]
+fn with_non_blocking<L: PollAccept + ?Sized, R>(listener: &L, func: impl FnOnce(bool) -> io::Result<R>) -> io::Result<R> {
+    let handle = listener.raw_handle();
+    {
+        let mut guard = NON_BLOCKING.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(index) = guard.iter().position(|(h, count)| *h == handle && *count == 0) {
+            //A previous call could not switch the listener back, it is reported before anything is accepted.
+            listener.set_nonblocking(false)?;
+            guard.swap_remove(index);
+        }
+
+        if let Some(entry) = guard.iter_mut().find(|(h, _)| *h == handle) {
+            entry.1 += 1;
+        } else {
+            if sys::is_nonblocking(handle)? {
+                //The user made the listener non-blocking, so we leave it and the stream alone.
+                drop(guard);
+                return func(false);
+            }
+
+            listener.set_nonblocking(true)?;
+            guard.push((handle, 1));
+        }
+    }
+
+    let result = func(true);
+
+    //The lock must be held while restoring, otherwise a concurrent call could switch to non-blocking in between.
+    {
+        let mut guard = NON_BLOCKING.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(index) = guard.iter().position(|(h, _)| *h == handle) {
+            guard[index].1 -= 1;
+            //On failure the entry stays with a count of 0, the next call retries and reports the error.
+            if guard[index].1 == 0 && listener.set_nonblocking(false).is_ok() {
+                guard.swap_remove(index);
+            }
+        }
+    }
+
+    result
+}
+
+impl PollAccept for TcpListener {
+    type Stream = TcpStream;
+    type Addr = SocketAddr;
+
+    fn accept(&self) -> io::Result<(Self::Stream, Self::Addr)> {
+        Self::accept(self)
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        Self::set_nonblocking(self, nonblocking)
+    }
+
+    fn set_stream_nonblocking(stream: &Self::Stream, nonblocking: bool) -> io::Result<()> {
+        stream.set_nonblocking(nonblocking)
+    }
+}
+
+#[cfg(unix)]
+impl PollAccept for std::os::unix::net::UnixListener {
+    type Stream = std::os::unix::net::UnixStream;
+    type Addr = std::os::unix::net::SocketAddr;
+
+    fn accept(&self) -> io::Result<(Self::Stream, Self::Addr)> {
+        Self::accept(self)
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        Self::set_nonblocking(self, nonblocking)
+    }
+
+    fn set_stream_nonblocking(stream: &Self::Stream, nonblocking: bool) -> io::Result<()> {
+        stream.set_nonblocking(nonblocking)
+    }
+}
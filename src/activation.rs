@@ -0,0 +1,234 @@
+//! Listeners inherited through systemd socket activation.
+
+use crate::{PollEx, RawHandle};
+use libc::{c_int, c_void, sockaddr_storage, socklen_t, AF_INET, AF_INET6, AF_UNIX, SOCK_STREAM, SOL_SOCKET, SO_ACCEPTCONN, SO_TYPE};
+use std::io;
+use std::net::TcpListener;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+use std::os::unix::net::UnixListener;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The first inherited handle, the others follow without gaps.
+const LISTEN_FDS_START: c_int = 3;
+
+/// Set once the inherited handles are owned by listeners, taking them again would close them twice.
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// A listener that was inherited through socket activation or received with `receive_listeners`.
+///
+/// A handle can also be checked and converted with `TryFrom<OwnedFd>`, which fails with `InvalidInput`
+/// if it is not a listening tcp or unix stream socket.
+#[derive(Debug)]
+pub enum ActivatedListener {
+    /// An `AF_INET` or `AF_INET6` stream socket.
+    Tcp(TcpListener),
+    /// An `AF_UNIX` stream socket.
+    Unix(UnixListener),
+}
+
+impl PollEx for ActivatedListener {
+    fn raw_handle(&self) -> RawHandle {
+        self.as_fd().as_raw_fd()
+    }
+}
+
+impl ActivatedListener {
+    /// Wraps a handle whose address family was returned by `listener_family`.
+    pub(crate) fn from_validated(fd: OwnedFd, family: c_int) -> Self {
+        if family == AF_UNIX {
+            Self::Unix(UnixListener::from(fd))
+        } else {
+            Self::Tcp(TcpListener::from(fd))
+        }
+    }
+}
+
+impl TryFrom<OwnedFd> for ActivatedListener {
+    type Error = io::Error;
+
+    fn try_from(fd: OwnedFd) -> io::Result<Self> {
+        let family = listener_family(fd.as_raw_fd())?;
+        Ok(Self::from_validated(fd, family))
+    }
+}
+
+impl From<ActivatedListener> for OwnedFd {
+    fn from(listener: ActivatedListener) -> Self {
+        match listener {
+            ActivatedListener::Tcp(listener) => listener.into(),
+            ActivatedListener::Unix(listener) => listener.into(),
+        }
+    }
+}
+
+impl AsFd for ActivatedListener {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        match self {
+            Self::Tcp(listener) => listener.as_fd(),
+            Self::Unix(listener) => listener.as_fd(),
+        }
+    }
+}
+
+/// Returns the listeners that the service manager passed to this process, together with their names.
+///
+/// This implements the protocol of `sd_listen_fds_with_names`: `LISTEN_PID` must be the id of this
+/// process and `LISTEN_FDS` the amount of handles, which start at 3. `LISTEN_FDNAMES` contains the
+/// colon separated names of the handles, without it every name is "unknown".
+///
+/// Every handle is checked to be a listening stream socket with `SO_TYPE`, `SO_ACCEPTCONN` and
+/// `getsockname`, then it is marked close-on-exec and owned by the returned listener.
+/// If a check fails no handle is taken.
+///
+/// An empty Vec is returned if the variables are not set or meant for another process.
+/// If `unset_env` is true the variables are removed so child processes do not see them.
+/// The handles can only be taken once, later calls return an empty Vec.
+///
+/// Removing the variables is not thread safe, other threads must not read or change the environment
+/// at the same time, this includes libc functions like `getaddrinfo` that read it internally.
+/// Only pass true while the process is single-threaded, for example at the start of `main`,
+/// otherwise pass false and leave the variables in place.
+///
+/// ## Example
+/// ```rust
+/// use listener_poll::{listen_fds, ActivatedListener, PollEx};
+///
+/// for (name, listener) in listen_fds(true).unwrap() {
+///     match listener {
+///         ActivatedListener::Tcp(listener) => println!("{name}: tcp {:?}", listener.local_addr()),
+///         ActivatedListener::Unix(listener) => println!("{name}: unix {:?}", listener.local_addr()),
+///     }
+/// }
+/// ```
+///
+/// # Errors
+/// `InvalidData` if a variable cannot be parsed or the amount of names does not match the amount of handles.
+/// `InvalidInput` if a handle is not a listening tcp or unix stream socket.
+/// Operating system and implementation-specific errors.
+///
+pub fn listen_fds(unset_env: bool) -> io::Result<Vec<(String, ActivatedListener)>> {
+    let listeners = take_listen_fds();
+    if unset_env {
+        std::env::remove_var("LISTEN_PID");
+        std::env::remove_var("LISTEN_FDS");
+        std::env::remove_var("LISTEN_FDNAMES");
+    }
+
+    listeners
+}
+
+/// Validates and takes the inherited handles.
+fn take_listen_fds() -> io::Result<Vec<(String, ActivatedListener)>> {
+    //Once taken the handles may be closed and their numbers reused, so nothing is checked again.
+    if TAKEN.load(Ordering::SeqCst) {
+        return Ok(Vec::new());
+    }
+
+    let Some(pid) = env_number("LISTEN_PID")? else {
+        return Ok(Vec::new());
+    };
+
+    if pid != std::process::id() {
+        return Ok(Vec::new());
+    }
+
+    let Some(count) = env_number("LISTEN_FDS")? else {
+        return Ok(Vec::new());
+    };
+
+    let count = c_int::try_from(count)
+        .ok()
+        .filter(|count| count.checked_add(LISTEN_FDS_START).is_some())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "LISTEN_FDS is too large"))?;
+
+    let names = match std::env::var("LISTEN_FDNAMES") {
+        Ok(names) => names.split(':').map(str::to_string).collect::<Vec<_>>(),
+        Err(std::env::VarError::NotPresent) => vec!["unknown".to_string(); count.unsigned_abs() as usize],
+        Err(std::env::VarError::NotUnicode(_)) => {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "LISTEN_FDNAMES is not valid unicode"))
+        }
+    };
+
+    if names.len() != count.unsigned_abs() as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "LISTEN_FDNAMES does not contain one name per handle"));
+    }
+
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+
+    let fds = (LISTEN_FDS_START..LISTEN_FDS_START + count).collect::<Vec<_>>();
+    let mut families = Vec::with_capacity(fds.len());
+    for fd in &fds {
+        families.push(listener_family(*fd)?);
+    }
+
+    //Only set after the checks, so a call that failed can be retried.
+    if TAKEN.swap(true, Ordering::SeqCst) {
+        return Ok(Vec::new());
+    }
+
+    let mut listeners = Vec::with_capacity(fds.len());
+    for ((fd, family), name) in fds.into_iter().zip(families).zip(names) {
+        //The handle was validated above and is only taken once because of TAKEN.
+        let listener = ActivatedListener::from_validated(unsafe { OwnedFd::from_raw_fd(fd) }, family);
+
+        //Service managers pass the handles without close-on-exec.
+        if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        listeners.push((name, listener));
+    }
+
+    Ok(listeners)
+}
+
+/// Parses an environment variable as a number, returns None if it is not set.
+fn env_number(name: &str) -> io::Result<Option<u32>> {
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("{name} is not a number"))),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(std::env::VarError::NotUnicode(_)) => Err(io::Error::new(io::ErrorKind::InvalidData, format!("{name} is not a number"))),
+    }
+}
+
+/// Returns the address family of a handle if it is a listening stream socket of a family that has a std listener.
+pub fn listener_family(fd: c_int) -> io::Result<c_int> {
+    let not_a_listener = || io::Error::new(io::ErrorKind::InvalidInput, format!("handle {fd} is not a listening stream socket"));
+
+    if socket_option(fd, SO_TYPE)? != SOCK_STREAM || socket_option(fd, SO_ACCEPTCONN)? == 0 {
+        return Err(not_a_listener());
+    }
+
+    let mut address = unsafe { std::mem::zeroed::<sockaddr_storage>() };
+    let mut length = socklen_t::try_from(std::mem::size_of::<sockaddr_storage>())
+        .expect("Unreachable: sockaddr_storage is larger than socklen_t::MAX");
+    if unsafe { libc::getsockname(fd, std::ptr::addr_of_mut!(address).cast(), &mut length) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    match c_int::from(address.ss_family) {
+        family @ (AF_INET | AF_INET6 | AF_UNIX) => Ok(family),
+        _ => Err(not_a_listener()),
+    }
+}
+
+/// Reads an integer socket option, fails with `InvalidInput` if the handle is not a socket.
+fn socket_option(fd: c_int, option: c_int) -> io::Result<c_int> {
+    let mut value: c_int = 0;
+    let mut length = socklen_t::try_from(std::mem::size_of::<c_int>()).expect("Unreachable: c_int is larger than socklen_t::MAX");
+    if unsafe { libc::getsockopt(fd, SOL_SOCKET, option, std::ptr::addr_of_mut!(value).cast::<c_void>(), &mut length) } < 0 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() == Some(libc::ENOTSOCK) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("handle {fd} is not a socket")));
+        }
+
+        return Err(err);
+    }
+
+    Ok(value)
+}
@@ -0,0 +1,324 @@
+//! Futures for accepting connections without an async runtime.
+
+use crate::{sys, Backend, Interest, PollAccept, PollInterrupt, RawHandle};
+use futures_core::Stream;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::Duration;
+
+/// The reactor that is shared by every `AsyncListener`, it is started by the first listener.
+static REACTOR: Mutex<Option<Arc<Reactor>>> = Mutex::new(None);
+
+/// A future that waits for a handle to become ready.
+#[derive(Debug)]
+struct Registration {
+    /// identifies the registration so its future can remove it.
+    id: u64,
+    /// the handle.
+    handle: RawHandle,
+    /// what the handle is polled for.
+    interest: Interest,
+    /// woken once the handle is ready.
+    waker: Waker,
+}
+
+/// State that is guarded by the mutex of the reactor.
+#[derive(Debug)]
+struct Registrations {
+    /// the registered handles.
+    list: Vec<Registration>,
+    /// the id of the next registration.
+    next_id: u64,
+    /// errors of polls that failed, by the id of the registrations that were polled.
+    errors: Vec<(u64, io::Error)>,
+}
+
+/// Thread that polls every registered handle and wakes the futures of the ready ones.
+#[derive(Debug)]
+struct Reactor {
+    /// the handles that futures are waiting for.
+    registrations: Mutex<Registrations>,
+    /// wakes the reactor thread when a registration was added.
+    interrupt: PollInterrupt,
+}
+
+impl Reactor {
+    /// Returns the reactor, it is started on the first call.
+    fn get() -> io::Result<Arc<Self>> {
+        let mut guard = REACTOR.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(reactor) = guard.as_ref() {
+            return Ok(Arc::clone(reactor));
+        }
+
+        let reactor = Arc::new(Self {
+            registrations: Mutex::new(Registrations {
+                list: Vec::new(),
+                next_id: 0,
+                errors: Vec::new(),
+            }),
+            interrupt: PollInterrupt::new()?,
+        });
+
+        let thread_reactor = Arc::clone(&reactor);
+        thread::Builder::new()
+            .name("listener_poll reactor".to_string())
+            .spawn(move || thread_reactor.run())?;
+
+        *guard = Some(Arc::clone(&reactor));
+        drop(guard);
+        Ok(reactor)
+    }
+
+    /// Locks the registrations, a poisoned lock is harmless because every modification is completed before it can panic.
+    fn lock(&self) -> MutexGuard<'_, Registrations> {
+        self.registrations.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Registers a handle, the waker is woken once the handle is ready. Returns the id of the registration.
+    fn register(&self, handle: RawHandle, interest: Interest, waker: Waker) -> io::Result<u64> {
+        let mut guard = self.lock();
+        let id = guard.next_id;
+        guard.next_id = guard.next_id.wrapping_add(1);
+        guard.list.push(Registration {
+            id,
+            handle,
+            interest,
+            waker,
+        });
+        drop(guard);
+
+        self.interrupt.interrupt()?;
+        Ok(id)
+    }
+
+    /// Removes a registration if the reactor did not remove it yet.
+    /// Returns the error if the reactor failed to poll the handle.
+    fn deregister(&self, id: u64) -> Option<io::Error> {
+        let mut guard = self.lock();
+        guard.list.retain(|registration| registration.id != id);
+        let index = guard.errors.iter().position(|(failed, _)| *failed == id)?;
+        Some(guard.errors.swap_remove(index).1)
+    }
+
+    /// The loop of the reactor thread, it never returns.
+    fn run(&self) {
+        loop {
+            //Registrations that are added after the reset trigger the interrupt again.
+            if let Err(err) = self.interrupt.reset() {
+                self.fail(None, &err);
+                //Avoids spinning if the error persists.
+                thread::sleep(Duration::from_millis(10));
+                continue;
+            }
+
+            let (ids, mut fds) = match self.poll_structures() {
+                Ok(structures) => structures,
+                Err(err) => {
+                    self.fail(None, &err);
+                    continue;
+                }
+            };
+
+            match Backend::native().poll_fds(&mut fds, None) {
+                Ok(_) => {}
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    self.fail(Some(&ids), &err);
+                    continue;
+                }
+            }
+
+            let mut guard = self.lock();
+            for (id, fd) in ids.into_iter().zip(fds) {
+                if !sys::is_ready(fd) {
+                    continue;
+                }
+
+                if let Some(index) = guard.list.iter().position(|registration| registration.id == id) {
+                    guard.list.swap_remove(index).waker.wake();
+                }
+            }
+        }
+    }
+
+    /// Returns the ids of all registrations and the poll structures of their handles followed by the interrupt.
+    fn poll_structures(&self) -> io::Result<(Vec<u64>, Vec<sys::PollFd>)> {
+        let guard = self.lock();
+        let mut ids = Vec::with_capacity(guard.list.len());
+        let mut fds = Vec::with_capacity(guard.list.len() + 1);
+        for registration in &guard.list {
+            ids.push(registration.id);
+            fds.push(sys::new_poll_fd(registration.handle, registration.interest)?);
+        }
+        drop(guard);
+
+        fds.push(sys::new_poll_fd(self.interrupt.raw_handle(), Interest::READ)?);
+        Ok((ids, fds))
+    }
+
+    /// Removes the registrations with the ids, or every registration if None, and wakes them with the error.
+    /// Registrations that were added after the failed poll are kept.
+    fn fail(&self, ids: Option<&[u64]>, err: &io::Error) {
+        let mut guard = self.lock();
+        let (failed, kept) = std::mem::take(&mut guard.list)
+            .into_iter()
+            .partition::<Vec<_>, _>(|registration| ids.map_or(true, |ids| ids.contains(&registration.id)));
+        guard.list = kept;
+        for registration in &failed {
+            guard.errors.push((registration.id, io::Error::new(err.kind(), err.to_string())));
+        }
+        drop(guard);
+
+        for registration in failed {
+            registration.waker.wake();
+        }
+    }
+}
+
+/// Listener whose `accept` returns a future, for async code without a runtime.
+///
+/// The listener is switched to non-blocking, a future that cannot accept yet registers the listener
+/// with a reactor thread that is shared by all `AsyncListener`s. The reactor polls every registered
+/// listener with the backend of the crate and wakes the future once its listener is ready.
+/// The reactor thread is started by the first `AsyncListener` and runs until the process exits.
+///
+/// The returned streams are blocking.
+///
+/// ## Example
+/// ```rust
+/// use std::net::{TcpListener, TcpStream};
+/// use listener_poll::AsyncListener;
+///
+/// let listener = AsyncListener::new(TcpListener::bind(("127.0.0.1", 0)).unwrap()).unwrap();
+/// let _stream = TcpStream::connect(listener.get_ref().local_addr().unwrap()).unwrap();
+/// let (_accepted, _addr) = futures_executor::block_on(listener.accept()).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct AsyncListener<L: PollAccept> {
+    /// the non-blocking listener.
+    listener: L,
+    /// the shared reactor.
+    reactor: Arc<Reactor>,
+}
+
+impl<L: PollAccept> AsyncListener<L> {
+    /// Switches the listener to non-blocking and starts the reactor thread if it is not running yet.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn new(listener: L) -> io::Result<Self> {
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            reactor: Reactor::get()?,
+        })
+    }
+
+    /// Returns the listener.
+    pub const fn get_ref(&self) -> &L {
+        &self.listener
+    }
+
+    /// Switches the listener back to blocking and returns it.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn into_inner(self) -> io::Result<L> {
+        self.listener.set_nonblocking(false)?;
+        Ok(self.listener)
+    }
+
+    /// Returns a future that resolves to the next connection.
+    pub const fn accept(&self) -> Accept<'_, L> {
+        Accept {
+            listener: self,
+            registration: None,
+        }
+    }
+
+    /// Returns a stream of incoming connections, it never ends.
+    pub const fn incoming(&self) -> Incoming<'_, L> {
+        Incoming { accept: self.accept() }
+    }
+
+    /// Accepts a connection or registers the waker with the reactor if there is none.
+    /// The previous registration of the caller, if any, is replaced.
+    fn poll_accept(&self, registration: &mut Option<u64>, cx: &Context<'_>) -> Poll<io::Result<(L::Stream, L::Addr)>> {
+        if let Some(id) = registration.take() {
+            if let Some(err) = self.reactor.deregister(id) {
+                return Poll::Ready(Err(err));
+            }
+        }
+
+        match self.listener.accept() {
+            Ok(connection) => {
+                //Some operating systems let the stream inherit the non-blocking state of the listener.
+                if let Err(err) = L::set_stream_nonblocking(&connection.0, false) {
+                    return Poll::Ready(Err(err));
+                }
+
+                Poll::Ready(Ok(connection))
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                match self.reactor.register(self.listener.raw_handle(), self.listener.interest(), cx.waker().clone()) {
+                    Ok(id) => {
+                        *registration = Some(id);
+                        Poll::Pending
+                    }
+                    Err(err) => Poll::Ready(Err(err)),
+                }
+            }
+            Err(err) => Poll::Ready(Err(err)),
+        }
+    }
+}
+
+/// Future returned by `AsyncListener::accept`.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct Accept<'a, L: PollAccept> {
+    /// the listener.
+    listener: &'a AsyncListener<L>,
+    /// the registration with the reactor while the future waits.
+    registration: Option<u64>,
+}
+
+impl<L: PollAccept> Future for Accept<'_, L> {
+    type Output = io::Result<(L::Stream, L::Addr)>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        this.listener.poll_accept(&mut this.registration, cx)
+    }
+}
+
+impl<L: PollAccept> Drop for Accept<'_, L> {
+    fn drop(&mut self) {
+        if let Some(id) = self.registration.take() {
+            _ = self.listener.reactor.deregister(id);
+        }
+    }
+}
+
+/// Stream returned by `AsyncListener::incoming`.
+#[derive(Debug)]
+#[must_use = "streams do nothing unless polled"]
+pub struct Incoming<'a, L: PollAccept> {
+    /// the future of the next connection, it is reused for every connection.
+    accept: Accept<'a, L>,
+}
+
+impl<L: PollAccept> Stream for Incoming<'_, L> {
+    type Item = io::Result<(L::Stream, L::Addr)>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let accept = &mut self.get_mut().accept;
+        accept.listener.poll_accept(&mut accept.registration, cx).map(Some)
+    }
+}
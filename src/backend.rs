@@ -0,0 +1,256 @@
+//! Selection of the operating system function that is used to poll.
+
+use crate::sys;
+use std::io;
+use std::time::Duration;
+
+/// The operating system function that is used to poll.
+///
+/// By default the best function that exists on the target is used, see `Backend::native`.
+/// Another backend can be forced for a `ListenerSet` or with `PollOptions`, for example because
+/// a seccomp policy forbids a syscall or to run the same tests against every backend.
+///
+/// ## Example
+/// ```rust
+/// use std::net::TcpListener;
+/// use std::time::Duration;
+/// use listener_poll::{Backend, ListenerSet};
+///
+/// let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+/// for backend in Backend::available() {
+///     let mut set = ListenerSet::with_backend(backend).unwrap();
+///     set.add(&listener).unwrap();
+///     assert!(set.poll(Some(Duration::from_millis(10))).unwrap().is_empty());
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Backend {
+    /// poll on unix and `WSAPoll` on Windows, timeouts are rounded up to milliseconds.
+    Poll,
+    /// ppoll, which has nanosecond timeouts. Not available on Apple, OpenBSD and Windows.
+    PPoll,
+    /// epoll, only available on Linux and Android.
+    /// A temporary epoll instance is created for every poll, use `EpollPoller` for persistent registrations.
+    Epoll,
+    /// select, only available on unix, timeouts are rounded up to microseconds.
+    /// Handles must be below `FD_SETSIZE` (usually 1024), otherwise polling fails with `InvalidInput`.
+    Select,
+    /// `io_uring`, only available on Linux with the `io-uring` feature and if the kernel allows it.
+    /// A temporary ring is created for every poll, use `UringPoller` for persistent registrations.
+    IoUring,
+}
+
+impl Backend {
+    /// Returns the backend that is used unless another one is selected.
+    /// This is ppoll where it exists and poll otherwise, select is only used if it is selected explicitly.
+    #[must_use]
+    pub const fn native() -> Self {
+        if cfg!(all(unix, not(target_vendor = "apple"), not(target_os = "openbsd"))) {
+            Self::PPoll
+        } else {
+            Self::Poll
+        }
+    }
+
+    /// Returns every backend that is available, in declaration order.
+    ///
+    /// Each backend is probed by calling it once, a backend that is compiled in but forbidden
+    /// at runtime, for example by a seccomp policy that returns `EPERM` or `ENOSYS`, is not returned.
+    /// Seccomp policies that kill the process instead cannot be probed.
+    #[must_use]
+    pub fn available() -> Vec<Self> {
+        [Self::Poll, Self::PPoll, Self::Epoll, Self::Select, Self::IoUring]
+            .into_iter()
+            .filter(|backend| backend.is_available())
+            .collect()
+    }
+
+    /// Returns true if the backend is compiled in and usable at runtime.
+    #[must_use]
+    //On Windows only constant arms are left, probing calls a function on every other target.
+    #[cfg_attr(windows, allow(clippy::missing_const_for_fn))]
+    pub fn is_available(self) -> bool {
+        match self {
+            #[cfg(unix)]
+            Self::Poll => unsafe { libc::poll(std::ptr::null_mut(), 0, 0) >= 0 },
+            #[cfg(windows)]
+            Self::Poll => true,
+            #[cfg(all(unix, not(target_vendor = "apple"), not(target_os = "openbsd")))]
+            Self::PPoll => {
+                let time = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+                unsafe { libc::ppoll(std::ptr::null_mut(), 0, &time, std::ptr::null()) >= 0 }
+            }
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Self::Epoll => crate::EpollPoller::new().is_ok(),
+            #[cfg(unix)]
+            Self::Select => {
+                let mut time = libc::timeval { tv_sec: 0, tv_usec: 0 };
+                let null = std::ptr::null_mut();
+                unsafe { libc::select(0, null, null, null, &mut time) >= 0 }
+            }
+            #[cfg(all(feature = "io-uring", any(target_os = "linux", target_os = "android")))]
+            Self::IoUring => crate::UringPoller::new().map_or(false, |poller| poller.is_io_uring()),
+            //Reachable on every target that lacks one of the backends.
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+
+    /// Polls the handles with this backend.
+    /// Returns the amount of handles that are ready.
+    pub(crate) fn poll_fds(self, fds: &mut [sys::PollFd], timeout: Option<Duration>) -> io::Result<usize> {
+        match self {
+            #[cfg(unix)]
+            Self::Poll => crate::unix_poll::poll_fds(fds, timeout),
+            #[cfg(windows)]
+            Self::Poll => crate::windows::poll_fds(fds, timeout),
+            #[cfg(all(unix, not(target_vendor = "apple"), not(target_os = "openbsd")))]
+            Self::PPoll => crate::unix_ppoll::poll_fds(fds, timeout),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Self::Epoll => linux::poll_epoll(fds, timeout),
+            #[cfg(unix)]
+            Self::Select => crate::unix_select::poll_fds(fds, timeout),
+            #[cfg(all(feature = "io-uring", any(target_os = "linux", target_os = "android")))]
+            Self::IoUring => linux::poll_uring(fds, timeout),
+            //Reachable on every target that lacks one of the backends.
+            #[allow(unreachable_patterns)]
+            _ => Err(io::Error::new(io::ErrorKind::Unsupported, "poll backend is not available on this target")),
+        }
+    }
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Self::native()
+    }
+}
+
+/// Adapters that poll a slice of poll structures with the persistent pollers.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod linux {
+    use crate::{EpollPoller, Events, Interest, PollEx, RawHandle, Readiness};
+    use libc::{c_short, pollfd, POLLERR, POLLHUP, POLLIN, POLLNVAL, POLLOUT, POLLPRI};
+    use std::io;
+    use std::time::Duration;
+
+    /// A handle taken from a poll structure.
+    struct Raw {
+        /// the handle.
+        fd: RawHandle,
+        /// the interest of the poll structure.
+        interest: Interest,
+    }
+
+    impl PollEx for Raw {
+        fn raw_handle(&self) -> RawHandle {
+            self.fd
+        }
+
+        fn interest(&self) -> Interest {
+            self.interest
+        }
+    }
+
+    /// Returns the handle and interest of a poll structure.
+    fn raw(fd: pollfd) -> Raw {
+        let interest = match (fd.events & POLLIN != 0, fd.events & POLLOUT != 0) {
+            (true, true) => Interest::READ | Interest::WRITE,
+            (false, true) => Interest::WRITE,
+            _ => Interest::READ,
+        };
+
+        Raw { fd: fd.fd, interest }
+    }
+
+    /// Converts readiness flags back into poll events.
+    fn revents(readiness: Readiness) -> c_short {
+        let mut revents = 0;
+        for (flag, event) in [
+            (Readiness::READABLE, POLLIN),
+            (Readiness::WRITABLE, POLLOUT),
+            (Readiness::PRIORITY, POLLPRI),
+            (Readiness::ERROR, POLLERR),
+            (Readiness::HANGUP, POLLHUP),
+            (Readiness::INVALID, POLLNVAL),
+        ] {
+            if readiness.contains(flag) {
+                revents |= event;
+            }
+        }
+
+        revents
+    }
+
+    /// Stores the events of a wait in the poll structures.
+    /// A handle that is in the slice several times is only registered once, all its structures receive the events.
+    fn store(fds: &mut [pollfd], events: &Events) -> usize {
+        for event in events {
+            let fd = fds[event.token()].fd;
+            for entry in fds.iter_mut().filter(|entry| entry.fd == fd) {
+                entry.revents = revents(event.readiness());
+            }
+        }
+
+        fds.iter().filter(|fd| fd.revents != 0).count()
+    }
+
+    /// Marks a handle that cannot be registered because it is not open like poll does.
+    /// Returns false for every other error.
+    fn store_invalid(fd: &mut pollfd, err: &io::Error) -> bool {
+        if err.raw_os_error() != Some(libc::EBADF) {
+            return false;
+        }
+
+        fd.revents = POLLNVAL;
+        true
+    }
+
+    /// Polls the handles with a temporary epoll instance.
+    pub fn poll_epoll(fds: &mut [pollfd], timeout: Option<Duration>) -> io::Result<usize> {
+        let poller = EpollPoller::new()?;
+        let mut invalid = 0;
+        for (index, fd) in fds.iter_mut().enumerate() {
+            fd.revents = 0;
+            let source = raw(*fd);
+            match poller.add(&source, index, source.interest) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(err) if store_invalid(fd, &err) => invalid += 1,
+                Err(err) => return Err(err),
+            }
+        }
+
+        //poll reports handles that are not open immediately.
+        if invalid != 0 {
+            return Ok(invalid);
+        }
+
+        let mut events = Events::with_capacity(fds.len());
+        poller.wait(&mut events, timeout)?;
+        Ok(store(fds, &events))
+    }
+
+    /// Polls the handles with a temporary ring.
+    #[cfg(feature = "io-uring")]
+    pub fn poll_uring(fds: &mut [pollfd], timeout: Option<Duration>) -> io::Result<usize> {
+        let poller = crate::UringPoller::new()?;
+        if !poller.is_io_uring() {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "io_uring is not available"));
+        }
+
+        for (index, fd) in fds.iter_mut().enumerate() {
+            fd.revents = 0;
+            let source = raw(*fd);
+            match poller.add(&source, index, source.interest) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        let mut events = Events::with_capacity(fds.len());
+        poller.wait(&mut events, timeout)?;
+        Ok(store(fds, &events))
+    }
+}
@@ -0,0 +1,266 @@
+//! Creation of listeners with socket options that std does not offer.
+
+use crate::socket::unix::{domain, new_socket, raw_addr, set_option, unix_addr};
+use libc::{c_int, sockaddr, sockaddr_un, socklen_t, AF_UNIX, SOCK_STREAM};
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+use std::time::Duration;
+
+/// Builder for `TcpListener`s and `UnixListener`s with control over the socket options.
+///
+/// The listeners are created with libc and are the std types, so they implement `PollEx` and `PollAccept`.
+/// Options that are not set keep the default of the operating system, except close-on-exec which is on by default like in std.
+///
+/// ## Example
+/// ```rust
+/// use std::time::Duration;
+/// use listener_poll::{ListenerBuilder, PollEx};
+///
+/// let listener = ListenerBuilder::new()
+///     .backlog(4096)
+///     .reuse_address(true)
+///     .only_v6(false)
+///     .bind_tcp("[::1]:0".parse().unwrap())
+///     .unwrap();
+///
+/// assert!(!listener.poll(Some(Duration::from_millis(10))).unwrap());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[must_use]
+pub struct ListenerBuilder {
+    /// the length of the queue of pending connections.
+    backlog: c_int,
+    /// `IPV6_V6ONLY`, None keeps the default of the operating system.
+    only_v6: Option<bool>,
+    /// `SO_REUSEADDR`.
+    reuse_address: bool,
+    /// `SO_REUSEPORT`.
+    reuse_port: bool,
+    /// `TCP_DEFER_ACCEPT`, the time that accept waits for data.
+    defer_accept: Option<Duration>,
+    /// `TCP_FASTOPEN`, the length of the queue of pending fast open requests.
+    fastopen: Option<u32>,
+    /// `SOCK_CLOEXEC`.
+    cloexec: bool,
+}
+
+impl ListenerBuilder {
+    /// Creates a builder with a backlog of 128 and close-on-exec, this is what std uses.
+    pub const fn new() -> Self {
+        Self {
+            backlog: 128,
+            only_v6: None,
+            reuse_address: false,
+            reuse_port: false,
+            defer_accept: None,
+            fastopen: None,
+            cloexec: true,
+        }
+    }
+
+    /// Sets the length of the queue of connections that were not accepted yet.
+    /// The operating system may cap it, on Linux at `net.core.somaxconn`.
+    pub const fn backlog(mut self, backlog: i32) -> Self {
+        self.backlog = backlog;
+        self
+    }
+
+    /// Sets `IPV6_V6ONLY`, false makes an IPv6 listener also accept IPv4 connections.
+    /// Only applies to IPv6 addresses.
+    pub const fn only_v6(mut self, only_v6: bool) -> Self {
+        self.only_v6 = Some(only_v6);
+        self
+    }
+
+    /// Sets `SO_REUSEADDR`, which allows binding while connections of a previous listener are in `TIME_WAIT`.
+    pub const fn reuse_address(mut self, reuse: bool) -> Self {
+        self.reuse_address = reuse;
+        self
+    }
+
+    /// Sets `SO_REUSEPORT`, which allows several listeners to bind the same address, see `ShardedListener`.
+    pub const fn reuse_port(mut self, reuse: bool) -> Self {
+        self.reuse_port = reuse;
+        self
+    }
+
+    /// Sets `TCP_DEFER_ACCEPT`, accept then only returns connections that sent data or waited for the timeout.
+    /// Only available on Linux and Android, binding fails with `Unsupported` elsewhere.
+    pub const fn defer_accept(mut self, timeout: Option<Duration>) -> Self {
+        self.defer_accept = timeout;
+        self
+    }
+
+    /// Sets `TCP_FASTOPEN` with the length of the queue of fast open requests that were not accepted yet.
+    /// Only available on Linux, Android, Apple and FreeBSD, binding fails with `Unsupported` elsewhere.
+    /// Apple and FreeBSD only use the length to enable fast open.
+    pub const fn fastopen(mut self, queue_length: Option<u32>) -> Self {
+        self.fastopen = queue_length;
+        self
+    }
+
+    /// Sets if the listener is closed when the process executes another program, on by default.
+    /// Listeners that are meant for a child process, for example for socket activation, turn this off.
+    pub const fn cloexec(mut self, cloexec: bool) -> Self {
+        self.cloexec = cloexec;
+        self
+    }
+
+    /// Returns the length of the queue of connections that were not accepted yet.
+    #[must_use]
+    pub const fn get_backlog(&self) -> i32 {
+        self.backlog
+    }
+
+    /// Returns `IPV6_V6ONLY`, None if the default of the operating system is kept.
+    #[must_use]
+    pub const fn get_only_v6(&self) -> Option<bool> {
+        self.only_v6
+    }
+
+    /// Returns if `SO_REUSEADDR` is set.
+    #[must_use]
+    pub const fn get_reuse_address(&self) -> bool {
+        self.reuse_address
+    }
+
+    /// Returns if `SO_REUSEPORT` is set.
+    #[must_use]
+    pub const fn get_reuse_port(&self) -> bool {
+        self.reuse_port
+    }
+
+    /// Returns the timeout of `TCP_DEFER_ACCEPT`.
+    #[must_use]
+    pub const fn get_defer_accept(&self) -> Option<Duration> {
+        self.defer_accept
+    }
+
+    /// Returns the queue length of `TCP_FASTOPEN`.
+    #[must_use]
+    pub const fn get_fastopen(&self) -> Option<u32> {
+        self.fastopen
+    }
+
+    /// Returns if the listener is closed when the process executes another program.
+    #[must_use]
+    pub const fn get_cloexec(&self) -> bool {
+        self.cloexec
+    }
+
+    /// Creates a tcp listener that is bound to the address.
+    ///
+    /// # Errors
+    /// `Unsupported` if an option is not available on the target.
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn bind_tcp(&self, addr: SocketAddr) -> io::Result<TcpListener> {
+        let fd = self.socket(domain(&addr))?;
+        if let (Some(only_v6), true) = (self.only_v6, addr.is_ipv6()) {
+            set_option(&fd, libc::IPPROTO_IPV6, libc::IPV6_V6ONLY, &c_int::from(only_v6))?;
+        }
+
+        if self.reuse_address {
+            set_option(&fd, libc::SOL_SOCKET, libc::SO_REUSEADDR, &1)?;
+        }
+
+        if self.reuse_port {
+            set_option(&fd, libc::SOL_SOCKET, libc::SO_REUSEPORT, &1)?;
+        }
+
+        let (storage, length) = raw_addr(&addr);
+        bind(&fd, std::ptr::addr_of!(storage).cast(), length)?;
+
+        if let Some(timeout) = self.defer_accept {
+            set_defer_accept(&fd, timeout)?;
+        }
+
+        if let Some(queue_length) = self.fastopen {
+            set_fastopen(&fd, queue_length)?;
+        }
+
+        self.listen(&fd)?;
+        Ok(TcpListener::from(fd))
+    }
+
+    /// Creates a unix listener that is bound to the path.
+    /// Only the backlog and close-on-exec apply to unix listeners.
+    ///
+    /// # Errors
+    /// `InvalidInput` if the path is too long for a unix socket address.
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn bind_unix<P: AsRef<Path>>(&self, path: P) -> io::Result<UnixListener> {
+        let (address, length) = unix_addr(path.as_ref().as_os_str().as_bytes())?;
+        self.bind_unix_raw(&address, length, || Ok(()))
+    }
+
+    /// Creates a unix listener that is bound to an address that was built by `unix_addr`.
+    /// The function runs between bind and listen, connections are refused until it returns.
+    pub(crate) fn bind_unix_raw(&self, address: &sockaddr_un, length: socklen_t, before_listen: impl FnOnce() -> io::Result<()>) -> io::Result<UnixListener> {
+        let fd = self.socket(AF_UNIX)?;
+        bind(&fd, (address as *const sockaddr_un).cast(), length)?;
+        before_listen()?;
+        self.listen(&fd)?;
+        Ok(UnixListener::from(fd))
+    }
+
+    /// Creates a stream socket of the family.
+    fn socket(&self, family: c_int) -> io::Result<OwnedFd> {
+        new_socket(family, SOCK_STREAM, self.cloexec)
+    }
+
+    /// Starts listening.
+    fn listen(&self, fd: &OwnedFd) -> io::Result<()> {
+        if unsafe { libc::listen(fd.as_raw_fd(), self.backlog) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for ListenerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Binds a socket to a raw address.
+fn bind(fd: &OwnedFd, address: *const sockaddr, length: socklen_t) -> io::Result<()> {
+    if unsafe { libc::bind(fd.as_raw_fd(), address, length) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Sets `TCP_DEFER_ACCEPT`, its value is in seconds.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn set_defer_accept(fd: &OwnedFd, timeout: Duration) -> io::Result<()> {
+    let seconds = c_int::try_from(timeout.as_secs() + u64::from(timeout.subsec_nanos() != 0)).unwrap_or(c_int::MAX);
+    set_option(fd, libc::IPPROTO_TCP, libc::TCP_DEFER_ACCEPT, &seconds)
+}
+
+/// `TCP_DEFER_ACCEPT` is not available.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn set_defer_accept(_fd: &OwnedFd, _timeout: Duration) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "TCP_DEFER_ACCEPT is not available on this target"))
+}
+
+/// Sets `TCP_FASTOPEN`.
+#[cfg(any(target_os = "linux", target_os = "android", target_vendor = "apple", target_os = "freebsd"))]
+fn set_fastopen(fd: &OwnedFd, queue_length: u32) -> io::Result<()> {
+    let value = c_int::try_from(queue_length).unwrap_or(c_int::MAX);
+    set_option(fd, libc::IPPROTO_TCP, libc::TCP_FASTOPEN, &value)
+}
+
+/// `TCP_FASTOPEN` is not available.
+#[cfg(not(any(target_os = "linux", target_os = "android", target_vendor = "apple", target_os = "freebsd")))]
+fn set_fastopen(_fd: &OwnedFd, _queue_length: u32) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "TCP_FASTOPEN is not available on this target"))
+}
@@ -0,0 +1,190 @@
+//! Connecting with a timeout that can be interrupted.
+
+use crate::{remaining, socket, FdPoller, Interest, ListenerSet, PollInterrupt};
+use std::io;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+/// Connects to the first address that accepts the connection, trying the addresses in order.
+///
+/// Each connection attempt is a non-blocking connect that is completed by polling the socket
+/// for writability, the timeout applies to all attempts together. A timeout of None waits forever.
+/// The interrupt, if any, aborts the connect immediately.
+/// Signals that interrupt the poll (EINTR) do not abort the connect, it continues with the remaining time.
+///
+/// Resolving the addresses is done by `ToSocketAddrs` and may block, pass `SocketAddr`s to avoid this.
+///
+/// The returned stream is blocking.
+///
+/// ## Example
+/// ```rust
+/// use std::net::TcpListener;
+/// use std::time::Duration;
+/// use listener_poll::connect_with_poll;
+///
+/// let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+/// let stream = connect_with_poll(listener.local_addr().unwrap(), Some(Duration::from_secs(5)), None).unwrap();
+/// assert_eq!(listener.local_addr().unwrap(), stream.peer_addr().unwrap());
+/// ```
+///
+/// # Errors
+/// `TimedOut` if the timeout elapsed, `Other` if the interrupt was triggered, unlike `Interrupted` this is never caused by a signal.
+/// Otherwise the error of the last connection attempt.
+///
+pub fn connect_with_poll<A: ToSocketAddrs>(
+    addrs: A,
+    timeout: Option<Duration>,
+    interrupt: Option<&PollInterrupt>,
+) -> io::Result<TcpStream> {
+    let addrs: Vec<SocketAddr> = addrs.to_socket_addrs()?.collect();
+    connect_impl(&addrs, None, timeout, interrupt)
+}
+
+/// Connects to the first address that accepts the connection using the happy eyeballs algorithm (RFC 8305).
+///
+/// The addresses are reordered to alternate between IPv6 and IPv4, starting with the family of the first address.
+/// A new connection attempt is started every `attempt_delay` while earlier attempts are still in progress,
+/// the first attempt that succeeds wins and all others are closed.
+///
+/// Otherwise this behaves like `connect_with_poll`.
+///
+/// # Errors
+/// `TimedOut` if the timeout elapsed, `Other` if the interrupt was triggered, unlike `Interrupted` this is never caused by a signal.
+/// Otherwise the error of the last connection attempt.
+///
+pub fn connect_happy_eyeballs<A: ToSocketAddrs>(
+    addrs: A,
+    attempt_delay: Duration,
+    timeout: Option<Duration>,
+    interrupt: Option<&PollInterrupt>,
+) -> io::Result<TcpStream> {
+    let mut first = Vec::new();
+    let mut second = Vec::new();
+    let mut first_is_v6 = None;
+    for addr in addrs.to_socket_addrs()? {
+        if *first_is_v6.get_or_insert_with(|| addr.is_ipv6()) == addr.is_ipv6() {
+            first.push(addr);
+        } else {
+            second.push(addr);
+        }
+    }
+
+    let mut addrs = Vec::with_capacity(first.len() + second.len());
+    let mut first = first.into_iter();
+    let mut second = second.into_iter();
+    loop {
+        match (first.next(), second.next()) {
+            (None, None) => break,
+            (a, b) => addrs.extend(a.into_iter().chain(b)),
+        }
+    }
+
+    connect_impl(&addrs, Some(attempt_delay), timeout, interrupt)
+}
+
+/// Connects to the addresses in order.
+/// Without an attempt delay the next attempt starts once the previous one failed,
+/// otherwise it also starts once the delay since the previous attempt elapsed.
+fn connect_impl(
+    addrs: &[SocketAddr],
+    attempt_delay: Option<Duration>,
+    timeout: Option<Duration>,
+    interrupt: Option<&PollInterrupt>,
+) -> io::Result<TcpStream> {
+    let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+    let mut last_err = None;
+    let mut pending: Vec<TcpStream> = Vec::new();
+    let mut next = addrs.iter();
+    let mut next_attempt = Instant::now();
+
+    loop {
+        let now = Instant::now();
+        let may_start = pending.is_empty() || (attempt_delay.is_some() && now >= next_attempt);
+        if may_start {
+            if let Some(addr) = next.next() {
+                match start_attempt(addr) {
+                    Ok((stream, true)) => return finish(stream),
+                    Ok((stream, false)) => {
+                        pending.push(stream);
+                        next_attempt = attempt_delay.and_then(|delay| now.checked_add(delay)).unwrap_or(now);
+                    }
+                    //A failed attempt lets the next attempt start immediately.
+                    Err(err) => last_err = Some(err),
+                }
+
+                continue;
+            }
+        }
+
+        if pending.is_empty() {
+            return Err(last_err.unwrap_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "could not resolve to any addresses")
+            }));
+        }
+
+        let mut wait = remaining(timeout, deadline);
+        if attempt_delay.is_some() && next.len() != 0 {
+            let until_next = next_attempt.saturating_duration_since(now);
+            wait = Some(wait.map_or(until_next, |wait| wait.min(until_next)));
+        }
+
+        let pollers: Vec<FdPoller<&TcpStream>> = pending
+            .iter()
+            .map(|stream| FdPoller::with_interest(stream, Interest::WRITE))
+            .collect();
+        let mut set = ListenerSet::new();
+        for poller in &pollers {
+            set.add(poller)?;
+        }
+
+        let ready = match interrupt {
+            Some(interrupt) => set.poll_interruptible(wait, interrupt).map(|ready| ready.ok_or_else(cancelled)),
+            None => set.poll(wait).map(Ok),
+        };
+
+        let ready = match ready {
+            Ok(ready) => ready?,
+            //A signal, the loop polls again with the remaining time.
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        drop(set);
+        drop(pollers);
+
+        for index in ready.into_iter().rev() {
+            let stream = pending.swap_remove(index);
+            match stream.take_error() {
+                Ok(None) => return finish(stream),
+                Ok(Some(err)) | Err(err) => {
+                    last_err = Some(err);
+                    next_attempt = Instant::now();
+                }
+            }
+        }
+
+        if remaining(timeout, deadline) == Some(Duration::ZERO) {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "connection timed out"));
+        }
+    }
+}
+
+/// The error that is returned when the `PollInterrupt` cancels a connect.
+/// It is not `Interrupted`, so it can be told apart from a signal.
+fn cancelled() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "connect was cancelled by PollInterrupt")
+}
+
+/// Creates a non-blocking socket and starts to connect it.
+/// Returns true if the connection was established immediately.
+fn start_attempt(addr: &SocketAddr) -> io::Result<(TcpStream, bool)> {
+    let stream = socket::new_tcp_socket(addr)?;
+    stream.set_nonblocking(true)?;
+    let connected = socket::start_connect(&stream, addr)?;
+    Ok((stream, connected))
+}
+
+/// Switches a connected stream back to blocking.
+fn finish(stream: TcpStream) -> io::Result<TcpStream> {
+    stream.set_nonblocking(false)?;
+    Ok(stream)
+}
@@ -0,0 +1,488 @@
+//! Linux epoll backend with persistent registrations.
+
+use crate::accept::accept_non_blocking;
+use crate::{remaining, Interest, PollAccept, PollEx, Readiness};
+use libc::{c_int, epoll_event, EPOLLERR, EPOLLET, EPOLLEXCLUSIVE, EPOLLHUP, EPOLLIN, EPOLLOUT, EPOLLPRI, EPOLLRDHUP, EPOLL_CLOEXEC};
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::ptr::null;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Set once `epoll_pwait2` failed with `ENOSYS`, or `EPERM` from seccomp filters that block unknown syscalls,
+/// all later waits use `epoll_wait` directly.
+static NO_EPOLL_PWAIT2: AtomicBool = AtomicBool::new(false);
+
+/// Converts an epoll flag constant into the type of `epoll_event.events`.
+//EPOLLET is the sign bit of c_int, the bit pattern is what matters.
+#[allow(clippy::cast_sign_loss)]
+const fn flag(flag: c_int) -> u32 {
+    flag as u32
+}
+
+/// A readiness event returned by a wait.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Event {
+    /// the token the handle was registered with.
+    token: usize,
+    /// what the handle is ready for.
+    readiness: Readiness,
+}
+
+impl Event {
+    /// Returns the token the handle was registered with.
+    #[must_use]
+    pub const fn token(&self) -> usize {
+        self.token
+    }
+
+    /// Returns what the handle is ready for.
+    #[must_use]
+    pub const fn readiness(&self) -> Readiness {
+        self.readiness
+    }
+}
+
+/// Buffer for the events returned by a wait, it is reused between waits to avoid allocations.
+pub struct Events {
+    /// the buffer that is handed to the kernel.
+    raw: Vec<epoll_event>,
+    /// the converted events of the last wait.
+    events: Vec<Event>,
+}
+
+impl Events {
+    /// Creates a buffer that receives at most capacity events per wait.
+    /// A capacity of 0 is treated as 1.
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            raw: vec![epoll_event { events: 0, u64: 0 }; capacity],
+            events: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Returns the maximum amount of events per wait.
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.raw.len()
+    }
+
+    /// Returns the amount of events of the last wait.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Returns true if the last wait returned no events.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Returns the events of the last wait.
+    pub fn iter(&self) -> std::slice::Iter<'_, Event> {
+        self.events.iter()
+    }
+
+    /// Removes all events.
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    /// Appends an event, returns false if the buffer is full.
+    #[cfg(feature = "io-uring")]
+    pub(crate) fn push(&mut self, token: usize, readiness: Readiness) -> bool {
+        if self.events.len() >= self.capacity() {
+            return false;
+        }
+
+        self.events.push(Event { token, readiness });
+        true
+    }
+
+    /// Converts the first count raw events into events.
+    fn fill(&mut self, count: usize) {
+        self.events.clear();
+        for raw in &self.raw[..count] {
+            //epoll_event is packed on some targets, the fields must be copied out.
+            let (events, data) = (raw.events, raw.u64);
+            self.events.push(Event {
+                token: usize::try_from(data).expect("Unreachable: epoll returned data that was not registered as usize token"),
+                readiness: readiness(events),
+            });
+        }
+    }
+}
+
+impl std::fmt::Debug for Events {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        //The raw buffer is scratch space for the kernel and not interesting.
+        f.debug_struct("Events")
+            .field("capacity", &self.capacity())
+            .field("events", &self.events)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a> IntoIterator for &'a Events {
+    type Item = &'a Event;
+    type IntoIter = std::slice::Iter<'a, Event>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Converts epoll events into readiness flags.
+pub fn readiness(events: u32) -> Readiness {
+    let mut readiness = Readiness::EMPTY;
+    for (event, flag_value) in [
+        (flag(EPOLLIN), Readiness::READABLE),
+        (flag(EPOLLOUT), Readiness::WRITABLE),
+        (flag(EPOLLPRI), Readiness::PRIORITY),
+        (flag(EPOLLERR), Readiness::ERROR),
+        (flag(EPOLLHUP | EPOLLRDHUP), Readiness::HANGUP),
+    ] {
+        if events & event != 0 {
+            readiness |= flag_value;
+        }
+    }
+
+    readiness
+}
+
+/// Converts an interest into epoll events.
+pub const fn interest_events(interest: Interest) -> u32 {
+    let mut events = 0;
+    if interest.is_readable() {
+        events |= flag(EPOLLIN);
+    }
+
+    if interest.is_writable() {
+        events |= flag(EPOLLOUT);
+    }
+
+    events
+}
+
+/// Poller that registers handles once and waits for all of them with epoll.
+///
+/// Unlike `ListenerSet` the kernel keeps the registrations between waits,
+/// which scales to many handles. Registrations can be changed while another thread waits.
+///
+/// A handle is removed automatically once every handle that refers to the same socket is closed.
+/// If the handle was duplicated, for example with `try_clone` or by a child process, the registration
+/// stays and keeps reporting events after closing this handle, so call `remove` before closing it.
+///
+/// ## Example
+/// ```rust
+/// use std::net::{TcpListener, TcpStream};
+/// use std::time::Duration;
+/// use listener_poll::{EpollPoller, Events, Interest};
+///
+/// let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+/// let poller = EpollPoller::new().unwrap();
+/// poller.add(&listener, 7, Interest::READ).unwrap();
+///
+/// let _stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+/// let mut events = Events::with_capacity(16);
+/// poller.wait(&mut events, Some(Duration::from_secs(5))).unwrap();
+/// assert_eq!(7, events.iter().next().unwrap().token());
+/// ```
+#[derive(Debug)]
+pub struct EpollPoller {
+    /// the epoll instance.
+    fd: OwnedFd,
+}
+
+impl EpollPoller {
+    /// Creates a new epoll instance without registrations.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::epoll_create1(EPOLL_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+        })
+    }
+
+    /// Registers a handle, waits report its token once it is ready for the interest.
+    ///
+    /// # Errors
+    /// `AlreadyExists` if the handle is already registered.
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn add<S: PollEx + ?Sized>(&self, source: &S, token: usize, interest: Interest) -> io::Result<()> {
+        self.ctl(libc::EPOLL_CTL_ADD, source.raw_handle(), token, interest_events(interest))
+    }
+
+    /// Registers a handle with `EPOLLEXCLUSIVE`.
+    ///
+    /// If the same handle is registered exclusively in several epoll instances, for example one per worker thread,
+    /// the kernel wakes only one (or a few) of the threads waiting on those instances instead of all of them.
+    /// This avoids the thundering herd when several threads wait for connections on the same listener.
+    /// The registration cannot be changed with `modify`, it must be removed and added again.
+    ///
+    /// Requires Linux 4.5 or later, older kernels ignore the flag and wake every waiter.
+    ///
+    /// # Errors
+    /// `AlreadyExists` if the handle is already registered.
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn add_exclusive<S: PollEx + ?Sized>(&self, source: &S, token: usize, interest: Interest) -> io::Result<()> {
+        self.ctl(libc::EPOLL_CTL_ADD, source.raw_handle(), token, interest_events(interest) | flag(EPOLLEXCLUSIVE))
+    }
+
+    /// Registers a handle in edge-triggered mode (`EPOLLET`).
+    ///
+    /// A wait reports the handle only when its readiness changes, not as long as it stays ready.
+    /// After an event the handle must be drained until it would block, for listeners use `PollAccept::drain_accept`,
+    /// otherwise the remaining connections are not reported again until another one arrives.
+    ///
+    /// # Errors
+    /// `AlreadyExists` if the handle is already registered.
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn add_edge_triggered<S: PollEx + ?Sized>(&self, source: &S, token: usize, interest: Interest) -> io::Result<()> {
+        self.ctl(libc::EPOLL_CTL_ADD, source.raw_handle(), token, interest_events(interest) | flag(EPOLLET))
+    }
+
+    /// Changes the token and interest of a registered handle.
+    /// Registrations made with `add_edge_triggered` become level-triggered.
+    ///
+    /// # Errors
+    /// `NotFound` if the handle is not registered.
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn modify<S: PollEx + ?Sized>(&self, source: &S, token: usize, interest: Interest) -> io::Result<()> {
+        self.ctl(libc::EPOLL_CTL_MOD, source.raw_handle(), token, interest_events(interest))
+    }
+
+    /// Removes a registered handle.
+    ///
+    /// # Errors
+    /// `NotFound` if the handle is not registered.
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn remove<S: PollEx + ?Sized>(&self, source: &S) -> io::Result<()> {
+        //Kernels before 2.6.9 require a non-null event even for EPOLL_CTL_DEL.
+        self.ctl(libc::EPOLL_CTL_DEL, source.raw_handle(), 0, 0)
+    }
+
+    /// Waits until at least one registered handle is ready or the timeout elapses.
+    /// The events are stored in the buffer, the amount of events is returned.
+    ///
+    /// Zero events are returned if the timeout elapses or an operating system dependent spurious wakeup occurs.
+    /// The timeout has nanosecond resolution on Linux 5.11 and later, older kernels round it up to milliseconds.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn wait(&self, events: &mut Events, timeout: Option<Duration>) -> io::Result<usize> {
+        let count = self.wait_raw(&mut events.raw, timeout)?;
+        events.fill(count);
+        Ok(count)
+    }
+
+    /// Calls `epoll_ctl`.
+    fn ctl(&self, op: c_int, fd: RawFd, token: usize, events: u32) -> io::Result<()> {
+        let mut event = epoll_event {
+            events,
+            u64: u64::try_from(token).expect("Unreachable: usize does not fit into u64"),
+        };
+
+        if unsafe { libc::epoll_ctl(self.fd.as_raw_fd(), op, fd, &mut event) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    /// Waits with `epoll_pwait2` if the kernel supports it and `epoll_wait` otherwise.
+    fn wait_raw(&self, raw: &mut [epoll_event], timeout: Option<Duration>) -> io::Result<usize> {
+        let max_events = c_int::try_from(raw.len()).unwrap_or(c_int::MAX);
+
+        if let Some(timeout) = timeout {
+            if !NO_EPOLL_PWAIT2.load(Ordering::Relaxed) {
+                let time = crate::unix_ppoll::to_timespec(timeout)?;
+                let count = unsafe {
+                    libc::syscall(
+                        libc::SYS_epoll_pwait2,
+                        self.fd.as_raw_fd(),
+                        raw.as_mut_ptr(),
+                        max_events,
+                        std::ptr::addr_of!(time),
+                        null::<libc::sigset_t>(),
+                        0usize,
+                    )
+                };
+                if count >= 0 {
+                    return Ok(usize::try_from(count).expect("Unreachable: non-negative c_long does not fit into usize"));
+                }
+
+                let err = io::Error::last_os_error();
+                //Older container seccomp profiles reject unknown syscalls with EPERM.
+                if !matches!(err.raw_os_error(), Some(libc::ENOSYS | libc::EPERM)) {
+                    return Err(err);
+                }
+
+                NO_EPOLL_PWAIT2.store(true, Ordering::Relaxed);
+            }
+        }
+
+        self.wait_millis(raw, max_events, timeout)
+    }
+
+    /// Waits with `epoll_wait`, which only supports millisecond timeouts.
+    fn wait_millis(&self, raw: &mut [epoll_event], max_events: c_int, timeout: Option<Duration>) -> io::Result<usize> {
+        const MAX_TIMEOUT_PER_CALL: u128 = c_int::MAX as u128;
+
+        let Some(mut ms) = timeout.map(crate::millis_rounded_up) else {
+            let count = unsafe { libc::epoll_wait(self.fd.as_raw_fd(), raw.as_mut_ptr(), max_events, -1) };
+            return usize::try_from(count).map_err(|_| io::Error::last_os_error());
+        };
+
+        while ms > MAX_TIMEOUT_PER_CALL {
+            ms -= MAX_TIMEOUT_PER_CALL;
+            let count = unsafe { libc::epoll_wait(self.fd.as_raw_fd(), raw.as_mut_ptr(), max_events, c_int::MAX) };
+            let count = usize::try_from(count).map_err(|_| io::Error::last_os_error())?;
+            if count != 0 {
+                return Ok(count);
+            }
+        }
+
+        let count = unsafe { libc::epoll_wait(self.fd.as_raw_fd(), raw.as_mut_ptr(), max_events, c_int::try_from(ms).expect("Unreachable: a conversion from u128 to c_int failed even tho the u128 is less than c_int::MAX")) };
+        usize::try_from(count).map_err(|_| io::Error::last_os_error())
+    }
+}
+
+impl AsFd for EpollPoller {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+/// Per-thread poller for a listener that is shared between several threads that accept connections.
+///
+/// Each thread creates its own `ExclusivePoller` for the shared listener. The listener is registered with
+/// `EPOLLEXCLUSIVE` in a private epoll instance, so an incoming connection wakes only one waiting thread
+/// instead of every thread that polls the listener.
+///
+/// Waking fewer threads does not guarantee that the woken thread wins the connection,
+/// a thread that is not waiting may accept it first. Use `accept_timeout` to never block in that case.
+///
+/// ## Example
+/// ```rust
+/// use std::net::{TcpListener, TcpStream};
+/// use std::sync::Arc;
+/// use std::thread;
+/// use std::time::Duration;
+/// use listener_poll::ExclusivePoller;
+///
+/// let listener = Arc::new(TcpListener::bind(("127.0.0.1", 0)).unwrap());
+/// let addr = listener.local_addr().unwrap();
+/// let workers: Vec<_> = (0..4).map(|_| {
+///     let listener = Arc::clone(&listener);
+///     thread::spawn(move || {
+///         let mut poller = ExclusivePoller::new(&*listener).unwrap();
+///         poller.accept_timeout(Some(Duration::from_millis(500))).unwrap().is_some()
+///     })
+/// }).collect();
+///
+/// let _stream = TcpStream::connect(addr).unwrap();
+/// let accepted = workers.into_iter().map(|jh| jh.join().unwrap()).filter(|a| *a).count();
+/// assert_eq!(1, accepted);
+/// ```
+#[derive(Debug)]
+pub struct ExclusivePoller<'a, L: PollEx + ?Sized> {
+    /// the private epoll instance of this thread.
+    poller: EpollPoller,
+    /// buffer for a single event.
+    events: Events,
+    /// the shared listener.
+    listener: &'a L,
+}
+
+impl<'a, L: PollEx + ?Sized> ExclusivePoller<'a, L> {
+    /// Creates a private epoll instance and registers the listener exclusively for the interest of the listener.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn new(listener: &'a L) -> io::Result<Self> {
+        let poller = EpollPoller::new()?;
+        poller.add_exclusive(listener, 0, listener.interest())?;
+        Ok(Self {
+            poller,
+            events: Events::with_capacity(1),
+            listener,
+        })
+    }
+
+    /// Returns the listener.
+    #[must_use]
+    pub const fn get_ref(&self) -> &'a L {
+        self.listener
+    }
+
+    /// This function returns Ok(true) if this thread was woken because the listener is ready.
+    ///
+    /// This function will return Ok(false) if the timeout elapses
+    /// or an operating system dependent spurious wakeup occurs.
+    /// A timeout of None waits forever.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn poll(&mut self, timeout: Option<Duration>) -> io::Result<bool> {
+        Ok(self.poller.wait(&mut self.events, timeout)? != 0)
+    }
+
+    /// Polls until this thread was woken because the listener is ready.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn poll_until_ready(&mut self) -> io::Result<()> {
+        while !self.poll(None)? {}
+        Ok(())
+    }
+
+    /// Accepts a connection, this function never blocks past the timeout.
+    ///
+    /// Behaves like `PollAccept::accept_timeout` but only wakes this thread if it is the one chosen by the kernel.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn accept_timeout(&mut self, timeout: Option<Duration>) -> io::Result<Option<(L::Stream, L::Addr)>>
+    where
+        L: PollAccept,
+    {
+        let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+
+        loop {
+            let remaining = remaining(timeout, deadline);
+
+            if self.poll(remaining)? {
+                match accept_non_blocking(self.listener) {
+                    Ok(connection) => return Ok(Some(connection)),
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                    Err(err) => return Err(err),
+                }
+            }
+
+            if remaining == Some(Duration::ZERO) {
+                return Ok(None);
+            }
+        }
+    }
+}
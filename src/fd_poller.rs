@@ -0,0 +1,124 @@
+//! Polling of arbitrary handles.
+
+use crate::{Interest, PollEx, RawHandle};
+
+/// Wrapper that implements `PollEx` for any type that owns or borrows a handle.
+///
+/// On unix this is every type that implements `AsFd`, for example `UdpSocket`, `UnixDatagram`,
+/// `TcpStream`, `UnixStream`, pipes, `ChildStdout` or `File`s of character devices.
+/// On Windows this is every type that implements `AsSocket`.
+///
+/// The interest selects if the poll waits for the handle to become readable, writable or either.
+///
+/// ## Example
+/// ```rust
+/// use std::net::UdpSocket;
+/// use std::time::Duration;
+/// use listener_poll::{FdPoller, Interest, PollEx};
+///
+/// let socket = UdpSocket::bind(("127.0.0.1", 0)).unwrap();
+/// let readable = FdPoller::new(&socket);
+/// assert!(!readable.poll(Some(Duration::from_millis(10))).unwrap());
+///
+/// let writable = FdPoller::with_interest(&socket, Interest::WRITE);
+/// assert!(writable.poll(Some(Duration::from_millis(10))).unwrap());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FdPoller<T> {
+    /// the wrapped value.
+    inner: T,
+    /// what a poll waits for.
+    interest: Interest,
+}
+
+impl<T> FdPoller<T> {
+    /// Wraps a value, polls wait until it is readable.
+    pub const fn new(inner: T) -> Self {
+        Self::with_interest(inner, Interest::READ)
+    }
+
+    /// Wraps a value, polls wait for the given interest.
+    pub const fn with_interest(inner: T, interest: Interest) -> Self {
+        Self { inner, interest }
+    }
+
+    /// Returns what a poll waits for.
+    pub const fn get_interest(&self) -> Interest {
+        self.interest
+    }
+
+    /// Changes what a poll waits for.
+    pub fn set_interest(&mut self, interest: Interest) {
+        self.interest = interest;
+    }
+
+    /// Returns a reference to the wrapped value.
+    pub const fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped value.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Returns the wrapped value.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+#[cfg(unix)]
+impl<T: std::os::fd::AsFd> PollEx for FdPoller<T> {
+    fn raw_handle(&self) -> RawHandle {
+        use std::os::fd::AsRawFd;
+        self.inner.as_fd().as_raw_fd()
+    }
+
+    fn interest(&self) -> Interest {
+        self.interest
+    }
+}
+
+#[cfg(unix)]
+impl<T: std::os::fd::AsFd> std::os::fd::AsFd for FdPoller<T> {
+    fn as_fd(&self) -> std::os::fd::BorrowedFd<'_> {
+        self.inner.as_fd()
+    }
+}
+
+#[cfg(windows)]
+impl<T: std::os::windows::io::AsSocket> PollEx for FdPoller<T> {
+    fn raw_handle(&self) -> RawHandle {
+        use std::os::windows::io::AsRawSocket;
+        self.inner.as_socket().as_raw_socket()
+    }
+
+    fn interest(&self) -> Interest {
+        self.interest
+    }
+}
+
+#[cfg(windows)]
+impl<T: std::os::windows::io::AsSocket> std::os::windows::io::AsSocket for FdPoller<T> {
+    fn as_socket(&self) -> std::os::windows::io::BorrowedSocket<'_> {
+        self.inner.as_socket()
+    }
+}
+
+/// Registers the wrapped handle with mio, the interest of the wrapper is ignored in favor of the interest of the registration.
+/// mio only supports its own socket types on Windows, so this is only implemented on unix.
+#[cfg(all(feature = "mio", unix))]
+impl<T: std::os::fd::AsFd> mio::event::Source for FdPoller<T> {
+    fn register(&mut self, registry: &mio::Registry, token: mio::Token, interests: mio::Interest) -> std::io::Result<()> {
+        mio::unix::SourceFd(&self.raw_handle()).register(registry, token, interests)
+    }
+
+    fn reregister(&mut self, registry: &mio::Registry, token: mio::Token, interests: mio::Interest) -> std::io::Result<()> {
+        mio::unix::SourceFd(&self.raw_handle()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> std::io::Result<()> {
+        mio::unix::SourceFd(&self.raw_handle()).deregister(registry)
+    }
+}
@@ -0,0 +1,300 @@
+//! Handing listeners to another process over a unix stream with `SCM_RIGHTS`.
+
+use crate::activation::listener_family;
+use crate::{ActivatedListener, EintrPolicy, PollEx};
+use libc::{c_int, c_uint, c_void, iovec, msghdr, SCM_RIGHTS, SOL_SOCKET};
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::net::UnixStream;
+use std::time::{Duration, Instant};
+
+/// Starts every handoff message, it guards against connecting the wrong processes.
+const MAGIC: [u8; 4] = *b"LPH1";
+
+/// Sent by the receiving process once it accepts on the listeners.
+const CONFIRM: u8 = 1;
+
+/// The maximum amount of handles in one message, this is `SCM_MAX_FD` of Linux.
+const MAX_LISTENERS: usize = 253;
+
+/// The maximum length of the name of a listener in bytes.
+const MAX_NAME_LEN: usize = 255;
+
+/// The maximum length of the names of one message, a longer length is not allocated.
+const MAX_NAMES_LEN: usize = MAX_LISTENERS * (4 + MAX_NAME_LEN);
+
+/// Size of the header that carries the handles: the magic, the amount of listeners and the length of the names.
+const HEADER_LEN: usize = 12;
+
+/// Sends listeners and their names to another process and waits until it confirms that it accepts on them.
+///
+/// The handles are duplicated into the receiving process with `SCM_RIGHTS`, both processes share the same
+/// sockets afterward, so no connection is lost during an upgrade. The receiving process calls
+/// `receive_listeners`, starts accepting and then calls `confirm_listeners`, only then this function returns.
+/// The calling process should stop polling and close its listeners after that.
+///
+/// ## Example
+/// ```rust
+/// use std::net::TcpListener;
+/// use std::os::unix::net::UnixStream;
+/// use std::thread;
+/// use std::time::{Duration, Instant};
+/// use listener_poll::{confirm_listeners, receive_listeners, send_listeners};
+///
+/// let (old, new) = UnixStream::pair().unwrap();
+/// let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+///
+/// let upgrade = thread::spawn(move || {
+///     let listeners = receive_listeners(&new).unwrap();
+///     //... start accepting on the listeners
+///     confirm_listeners(&new).unwrap();
+///     listeners
+/// });
+///
+/// send_listeners(&old, &[("web", &listener)], Some(Duration::from_secs(5))).unwrap();
+/// drop(listener);
+/// assert_eq!("web", upgrade.join().unwrap()[0].0);
+/// ```
+///
+/// # Errors
+/// `InvalidInput` if more than 253 listeners are sent or a name is longer than 255 bytes.
+/// `TimedOut` if the receiving process does not confirm before the timeout elapses.
+/// `UnexpectedEof` if the receiving process closes the stream before confirming.
+/// Operating system and implementation-specific errors.
+///
+pub fn send_listeners<L: PollEx + ?Sized>(stream: &UnixStream, listeners: &[(&str, &L)], timeout: Option<Duration>) -> io::Result<()> {
+    if listeners.len() > MAX_LISTENERS {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "too many listeners for one handoff"));
+    }
+
+    let mut names = Vec::new();
+    for (name, _) in listeners {
+        if name.len() > MAX_NAME_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the name of a listener is longer than 255 bytes"));
+        }
+
+        names.extend_from_slice(&length_prefix(name.len())?);
+        names.extend_from_slice(name.as_bytes());
+    }
+
+    let mut header = [0; HEADER_LEN];
+    header[..4].copy_from_slice(&MAGIC);
+    header[4..8].copy_from_slice(&length_prefix(listeners.len())?);
+    header[8..].copy_from_slice(&length_prefix(names.len())?);
+
+    let fds = listeners.iter().map(|(_, listener)| listener.raw_handle()).collect::<Vec<_>>();
+    let sent = send_with_fds(stream, &header, &fds)?;
+    let mut writer = stream;
+    writer.write_all(&header[sent..])?;
+    writer.write_all(&names)?;
+
+    if !wait_for_confirm(stream, timeout)? {
+        return Err(io::Error::new(io::ErrorKind::TimedOut, "the receiving process did not confirm the handoff"));
+    }
+
+    let mut confirm = [0];
+    let mut reader = stream;
+    reader.read_exact(&mut confirm)?;
+    if confirm[0] != CONFIRM {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "the receiving process sent an invalid confirmation"));
+    }
+
+    Ok(())
+}
+
+/// Waits until the confirmation can be read, returns false only once the full timeout has elapsed.
+/// Signals and spurious wakeups do not end the wait, the sending process would otherwise close listeners too early.
+fn wait_for_confirm(stream: &UnixStream, timeout: Option<Duration>) -> io::Result<bool> {
+    //A deadline that does not fit into an Instant is as good as forever.
+    if let Some(deadline) = timeout.and_then(|timeout| Instant::now().checked_add(timeout)) {
+        return stream.poll_deadline(deadline);
+    }
+
+    loop {
+        if stream.poll_with_policy(None, EintrPolicy::Retry)? {
+            return Ok(true);
+        }
+    }
+}
+
+/// Receives the listeners that another process sends with `send_listeners`.
+///
+/// Every handle is checked to be a listening tcp or unix stream socket and is close-on-exec.
+/// Call `confirm_listeners` once the listeners are accepting, the sending process waits for that.
+///
+/// # Errors
+/// `InvalidData` if the message was not sent by `send_listeners` or a handle is not a listening stream socket,
+/// all received handles are closed in that case.
+/// `UnexpectedEof` if the sending process closes the stream.
+/// Operating system and implementation-specific errors.
+///
+pub fn receive_listeners(stream: &UnixStream) -> io::Result<Vec<(String, ActivatedListener)>> {
+    let mut header = [0; HEADER_LEN];
+    let (received, fds) = receive_with_fds(stream, &mut header)?;
+    let mut reader = stream;
+    reader.read_exact(&mut header[received..])?;
+
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    if header[..4] != MAGIC {
+        return Err(invalid("the stream does not carry a listener handoff"));
+    }
+
+    let count = read_length(&header[4..8]);
+    if count != fds.len() {
+        return Err(invalid("the amount of received handles does not match the handoff"));
+    }
+
+    let names_length = read_length(&header[8..]);
+    if names_length > MAX_NAMES_LEN {
+        return Err(invalid("the names of the handoff are too long"));
+    }
+
+    let mut names = vec![0; names_length];
+    reader.read_exact(&mut names)?;
+
+    let mut listeners = Vec::with_capacity(count);
+    let mut rest = names.as_slice();
+    for fd in fds {
+        let name = rest
+            .get(..4)
+            .map(read_length)
+            .and_then(|length| rest.get(4..4 + length))
+            .ok_or_else(|| invalid("the names of the handoff are truncated"))?;
+        rest = &rest[4 + name.len()..];
+
+        let name = String::from_utf8(name.to_vec()).map_err(|_| invalid("the name of a listener is not valid unicode"))?;
+        let family = listener_family(fd.as_raw_fd()).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        listeners.push((name, ActivatedListener::from_validated(fd, family)));
+    }
+
+    Ok(listeners)
+}
+
+/// Tells the sending process that the received listeners are accepting, so it can stop polling them.
+///
+/// # Errors
+/// Operating system and implementation-specific errors.
+///
+pub fn confirm_listeners(stream: &UnixStream) -> io::Result<()> {
+    let mut writer = stream;
+    writer.write_all(&[CONFIRM])
+}
+
+/// Encodes a length as 4 little endian bytes.
+fn length_prefix(length: usize) -> io::Result<[u8; 4]> {
+    u32::try_from(length)
+        .map(u32::to_le_bytes)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "listener names are too long for a handoff"))
+}
+
+/// Decodes a length that was encoded by `length_prefix`.
+fn read_length(bytes: &[u8]) -> usize {
+    let bytes = <[u8; 4]>::try_from(bytes).expect("Unreachable: a length prefix is not 4 bytes long");
+    usize::try_from(u32::from_le_bytes(bytes)).unwrap_or(usize::MAX)
+}
+
+/// Returns the size of a control message buffer that has room for the amount of handles.
+fn control_space(fds: usize) -> usize {
+    let length = c_uint::try_from(fds * std::mem::size_of::<c_int>()).expect("Unreachable: too many handles for a control message");
+    unsafe { libc::CMSG_SPACE(length) as usize }
+}
+
+/// Sends the start of the data together with the handles, returns the amount of bytes that were sent.
+fn send_with_fds(stream: &UnixStream, data: &[u8], fds: &[c_int]) -> io::Result<usize> {
+    //u64 aligns the buffer for cmsghdr.
+    let mut control = vec![0_u64; (control_space(fds.len()) + 7) / 8];
+    let mut iov = iovec {
+        iov_base: data.as_ptr().cast_mut().cast::<c_void>(),
+        iov_len: data.len(),
+    };
+
+    let mut message = unsafe { std::mem::zeroed::<msghdr>() };
+    message.msg_iov = &mut iov;
+    message.msg_iovlen = 1;
+    if !fds.is_empty() {
+        message.msg_control = control.as_mut_ptr().cast();
+        message.msg_controllen = control_space(fds.len()) as _;
+        unsafe {
+            let header = libc::CMSG_FIRSTHDR(&message);
+            (*header).cmsg_level = SOL_SOCKET;
+            (*header).cmsg_type = SCM_RIGHTS;
+            (*header).cmsg_len = libc::CMSG_LEN(c_uint::try_from(std::mem::size_of_val(fds)).expect("Unreachable: checked by control_space")) as _;
+            std::ptr::copy_nonoverlapping(fds.as_ptr().cast::<u8>(), libc::CMSG_DATA(header), std::mem::size_of_val(fds));
+        }
+    }
+
+    loop {
+        let sent = unsafe { libc::sendmsg(stream.as_raw_fd(), &message, 0) };
+        match usize::try_from(sent) {
+            Ok(sent) => return Ok(sent),
+            Err(_) if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {}
+            Err(_) => return Err(io::Error::last_os_error()),
+        }
+    }
+}
+
+/// Receives the start of the data together with the handles that were sent with it.
+/// Returns the amount of bytes that were received and the handles, which are close-on-exec.
+fn receive_with_fds(stream: &UnixStream, data: &mut [u8]) -> io::Result<(usize, Vec<OwnedFd>)> {
+    let mut control = vec![0_u64; (control_space(MAX_LISTENERS) + 7) / 8];
+    let mut iov = iovec {
+        iov_base: data.as_mut_ptr().cast(),
+        iov_len: data.len(),
+    };
+
+    let mut message = unsafe { std::mem::zeroed::<msghdr>() };
+    message.msg_iov = &mut iov;
+    message.msg_iovlen = 1;
+    message.msg_control = control.as_mut_ptr().cast();
+    message.msg_controllen = control_space(MAX_LISTENERS) as _;
+
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd"))]
+    let flags = libc::MSG_CMSG_CLOEXEC;
+    #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd")))]
+    let flags = 0;
+
+    let received = loop {
+        let received = unsafe { libc::recvmsg(stream.as_raw_fd(), &mut message, flags) };
+        match usize::try_from(received) {
+            Ok(received) => break received,
+            Err(_) if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {}
+            Err(_) => return Err(io::Error::last_os_error()),
+        }
+    };
+
+    //Taking ownership first ensures that the handles are closed on every error below.
+    let mut fds = Vec::new();
+    unsafe {
+        let mut header = libc::CMSG_FIRSTHDR(&message);
+        while !header.is_null() {
+            if (*header).cmsg_level == SOL_SOCKET && (*header).cmsg_type == SCM_RIGHTS {
+                let data = libc::CMSG_DATA(header);
+                let length = (*header).cmsg_len as usize - (data as usize - header as usize);
+                for index in 0..length / std::mem::size_of::<c_int>() {
+                    let mut fd = [0; std::mem::size_of::<c_int>()];
+                    std::ptr::copy_nonoverlapping(data.add(index * fd.len()), fd.as_mut_ptr(), fd.len());
+                    fds.push(OwnedFd::from_raw_fd(c_int::from_ne_bytes(fd)));
+                }
+            }
+
+            header = libc::CMSG_NXTHDR(&message, header.cast_const());
+        }
+    }
+
+    if message.msg_flags & libc::MSG_CTRUNC != 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "the handles of the handoff were truncated"));
+    }
+
+    if received == 0 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the sending process closed the stream"));
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd")))]
+    for fd in &fds {
+        if unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok((received, fds))
+}
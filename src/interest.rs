@@ -0,0 +1,46 @@
+//! Which readiness a poll waits for.
+
+use std::ops::{BitOr, BitOrAssign};
+
+/// Set of readiness a poll waits for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Interest(u8);
+
+impl Interest {
+    /// Wait until reading or accepting does not block.
+    pub const READ: Self = Self(1);
+    /// Wait until writing does not block, for example because a non-blocking connect has completed.
+    pub const WRITE: Self = Self(1 << 1);
+
+    /// Returns true if all of other is part of this interest.
+    #[must_use]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns true if `READ` is part of this interest.
+    #[must_use]
+    pub const fn is_readable(self) -> bool {
+        self.contains(Self::READ)
+    }
+
+    /// Returns true if `WRITE` is part of this interest.
+    #[must_use]
+    pub const fn is_writable(self) -> bool {
+        self.contains(Self::WRITE)
+    }
+}
+
+impl BitOr for Interest {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Interest {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
@@ -0,0 +1,302 @@
+//! Handle to interrupt a blocked poll from another thread.
+
+use crate::RawHandle;
+use std::io;
+use std::sync::Arc;
+
+/// The result of a poll that can be interrupted by a `PollInterrupt`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PollOutcome {
+    /// A later call to `accept` returns a stream or error without blocking.
+    Ready,
+    /// The timeout elapsed or an operating system dependent spurious wakeup occurred.
+    TimedOut,
+    /// The `PollInterrupt` was triggered.
+    Interrupted,
+}
+
+/// Handle that makes an in-progress or future interruptible poll return `PollOutcome::Interrupted` immediately.
+///
+/// The handle can be cloned and sent to other threads, all clones refer to the same interrupt.
+/// Once triggered the interrupt stays triggered until `reset` is called.
+///
+/// This uses an eventfd on Linux and Android, a pipe on other unix systems
+/// and a connected loopback udp socket on Windows.
+///
+/// ## Example
+/// ```rust
+/// use std::net::TcpListener;
+/// use std::thread;
+/// use listener_poll::{PollEx, PollInterrupt, PollOutcome};
+///
+/// let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+/// let interrupt = PollInterrupt::new().unwrap();
+/// let remote = interrupt.clone();
+/// let jh = thread::spawn(move || remote.interrupt().unwrap());
+///
+/// assert_eq!(PollOutcome::Interrupted, listener.poll_until_ready_interruptible(&interrupt).unwrap());
+/// jh.join().unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct PollInterrupt {
+    /// the platform specific implementation shared by all clones.
+    inner: Arc<sys::Inner>,
+}
+
+impl PollInterrupt {
+    /// Creates a new interrupt that is not triggered.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            inner: Arc::new(sys::Inner::new()?),
+        })
+    }
+
+    /// Triggers the interrupt.
+    /// Every poll that uses this interrupt returns `PollOutcome::Interrupted` until `reset` is called.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn interrupt(&self) -> io::Result<()> {
+        self.inner.interrupt()
+    }
+
+    /// Clears the interrupt so it can be used again.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn reset(&self) -> io::Result<()> {
+        self.inner.reset()
+    }
+
+    /// Returns the handle that becomes readable once the interrupt is triggered.
+    pub(crate) fn raw_handle(&self) -> RawHandle {
+        self.inner.raw_handle()
+    }
+}
+
+/// eventfd based impl.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod sys {
+    use crate::RawHandle;
+    use libc::{c_void, eventfd, EFD_CLOEXEC, EFD_NONBLOCK};
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+    /// The eventfd, its counter is non-zero while the interrupt is triggered.
+    #[derive(Debug)]
+    pub struct Inner {
+        /// the eventfd.
+        fd: OwnedFd,
+    }
+
+    impl Inner {
+        /// Creates the eventfd.
+        pub fn new() -> io::Result<Self> {
+            let fd = unsafe { eventfd(0, EFD_CLOEXEC | EFD_NONBLOCK) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(Self {
+                fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            })
+        }
+
+        /// Increments the counter of the eventfd.
+        pub fn interrupt(&self) -> io::Result<()> {
+            let value: u64 = 1;
+            let count = unsafe {
+                libc::write(
+                    self.fd.as_raw_fd(),
+                    std::ptr::addr_of!(value).cast::<c_void>(),
+                    std::mem::size_of::<u64>(),
+                )
+            };
+            if count < 0 {
+                let err = io::Error::last_os_error();
+                //The counter is about to overflow, it is non-zero so the interrupt is already triggered.
+                if err.kind() != io::ErrorKind::WouldBlock {
+                    return Err(err);
+                }
+            }
+
+            Ok(())
+        }
+
+        /// Resets the counter of the eventfd to zero.
+        pub fn reset(&self) -> io::Result<()> {
+            let mut value: u64 = 0;
+            let count = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    std::ptr::addr_of_mut!(value).cast::<c_void>(),
+                    std::mem::size_of::<u64>(),
+                )
+            };
+            if count < 0 {
+                let err = io::Error::last_os_error();
+                //The counter is already zero.
+                if err.kind() != io::ErrorKind::WouldBlock {
+                    return Err(err);
+                }
+            }
+
+            Ok(())
+        }
+
+        /// Returns the eventfd.
+        pub fn raw_handle(&self) -> RawHandle {
+            self.fd.as_raw_fd()
+        }
+    }
+}
+
+/// self-pipe based impl.
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+mod sys {
+    use crate::RawHandle;
+    use libc::{c_int, c_void, fcntl, pipe, FD_CLOEXEC, F_GETFL, F_SETFD, F_SETFL, O_NONBLOCK};
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+    /// The pipe, it contains data while the interrupt is triggered.
+    #[derive(Debug)]
+    pub struct Inner {
+        /// the read end that is polled.
+        read: OwnedFd,
+        /// the write end.
+        write: OwnedFd,
+    }
+
+    /// Sets `FD_CLOEXEC` and `O_NONBLOCK` on a pipe end.
+    fn configure(fd: &OwnedFd) -> io::Result<()> {
+        unsafe {
+            if fcntl(fd.as_raw_fd(), F_SETFD, FD_CLOEXEC) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let flags = fcntl(fd.as_raw_fd(), F_GETFL);
+            if flags < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            if fcntl(fd.as_raw_fd(), F_SETFL, flags | O_NONBLOCK) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        Ok(())
+    }
+
+    impl Inner {
+        /// Creates the pipe.
+        pub fn new() -> io::Result<Self> {
+            let mut fds: [c_int; 2] = [-1, -1];
+            if unsafe { pipe(fds.as_mut_ptr()) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let (read, write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+            configure(&read)?;
+            configure(&write)?;
+            Ok(Self { read, write })
+        }
+
+        /// Writes a byte into the pipe.
+        pub fn interrupt(&self) -> io::Result<()> {
+            let value: u8 = 1;
+            let count = unsafe { libc::write(self.write.as_raw_fd(), std::ptr::addr_of!(value).cast::<c_void>(), 1) };
+            if count < 0 {
+                let err = io::Error::last_os_error();
+                //The pipe is full, so the interrupt is already triggered.
+                if err.kind() != io::ErrorKind::WouldBlock {
+                    return Err(err);
+                }
+            }
+
+            Ok(())
+        }
+
+        /// Reads from the pipe until it is empty.
+        pub fn reset(&self) -> io::Result<()> {
+            let mut buf = [0u8; 64];
+            loop {
+                let count = unsafe { libc::read(self.read.as_raw_fd(), buf.as_mut_ptr().cast::<c_void>(), buf.len()) };
+                if count < 0 {
+                    let err = io::Error::last_os_error();
+                    if err.kind() == io::ErrorKind::WouldBlock {
+                        return Ok(());
+                    }
+
+                    if err.kind() != io::ErrorKind::Interrupted {
+                        return Err(err);
+                    }
+                }
+            }
+        }
+
+        /// Returns the read end of the pipe.
+        pub fn raw_handle(&self) -> RawHandle {
+            self.read.as_raw_fd()
+        }
+    }
+}
+
+/// loopback udp socket based impl.
+#[cfg(windows)]
+mod sys {
+    use crate::RawHandle;
+    use std::io;
+    use std::net::UdpSocket;
+    use std::os::windows::io::AsRawSocket;
+
+    /// The udp socket is connected to itself, it has a pending datagram while the interrupt is triggered.
+    #[derive(Debug)]
+    pub struct Inner {
+        /// the socket.
+        socket: UdpSocket,
+    }
+
+    impl Inner {
+        /// Creates and connects the socket.
+        pub fn new() -> io::Result<Self> {
+            let socket = UdpSocket::bind(("127.0.0.1", 0))?;
+            socket.connect(socket.local_addr()?)?;
+            socket.set_nonblocking(true)?;
+            Ok(Self { socket })
+        }
+
+        /// Sends a datagram to the socket.
+        pub fn interrupt(&self) -> io::Result<()> {
+            match self.socket.send(&[1]) {
+                //The buffer is full, so the interrupt is already triggered.
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(()),
+                Err(err) => Err(err),
+                Ok(_) => Ok(()),
+            }
+        }
+
+        /// Receives datagrams until none are left.
+        pub fn reset(&self) -> io::Result<()> {
+            let mut buf = [0u8; 64];
+            loop {
+                match self.socket.recv(&mut buf) {
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                    Err(err) => return Err(err),
+                    Ok(_) => {}
+                }
+            }
+        }
+
+        /// Returns the socket.
+        pub fn raw_handle(&self) -> RawHandle {
+            self.socket.as_raw_socket()
+        }
+    }
+}
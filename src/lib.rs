@@ -27,6 +27,25 @@
 //!
 //! ```
 //!
+//! ## Stopping an accept loop without waiting for a timeout
+//! ```rust
+//! use std::io;
+//! use std::net::TcpListener;
+//!
+//! use listener_poll::{PollEx, PollInterrupt, PollOutcome};
+//!
+//! fn handle_accept(listener: TcpListener, interrupt: PollInterrupt) -> io::Result<()> {
+//!     loop {
+//!         //Another thread calls interrupt.interrupt() to stop this loop immediately.
+//!         if listener.poll_until_ready_interruptible(&interrupt)? == PollOutcome::Interrupted {
+//!             return Ok(());
+//!         }
+//!         let (_sock, _addr) = listener.accept()?;
+//!         //... probably thread::spawn or mpsc Sender::send
+//!     }
+//! }
+//! ```
+//!
 #![deny(
     clippy::correctness,
     clippy::perf,
@@ -45,10 +64,103 @@
 )]
 
 use std::io;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+mod accept;
+#[cfg(unix)]
+mod activation;
+#[cfg(feature = "async")]
+mod async_listener;
+mod backend;
+#[cfg(unix)]
+mod builder;
+mod connect;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod epoll;
+mod fd_poller;
+#[cfg(unix)]
+mod handoff;
+mod interest;
+mod interrupt;
+mod options;
+mod readiness;
+mod server;
+mod set;
+#[cfg(unix)]
+mod sharded;
+#[cfg(all(unix, not(target_vendor = "apple"), not(target_os = "openbsd")))]
+mod signal;
+mod socket;
+#[cfg(feature = "tokio")]
+mod tokio_interop;
+#[cfg(unix)]
+mod unix_builder;
+#[cfg(all(feature = "io-uring", any(target_os = "linux", target_os = "android")))]
+mod uring;
+
+pub use accept::PollAccept;
+#[cfg(unix)]
+pub use activation::{listen_fds, ActivatedListener};
+#[cfg(feature = "async")]
+pub use async_listener::{Accept, AsyncListener, Incoming};
+pub use backend::Backend;
+#[cfg(unix)]
+pub use builder::ListenerBuilder;
+pub use connect::{connect_happy_eyeballs, connect_with_poll};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use epoll::{EpollPoller, Event, Events, ExclusivePoller};
+pub use fd_poller::FdPoller;
+#[cfg(unix)]
+pub use handoff::{confirm_listeners, receive_listeners, send_listeners};
+pub use interest::Interest;
+pub use interrupt::{PollInterrupt, PollOutcome};
+pub use options::{EintrPolicy, PollOptions};
+pub use readiness::Readiness;
+pub use server::{AcceptLoop, ServerHandle, ShutdownReport};
+pub use set::ListenerSet;
+#[cfg(unix)]
+pub use sharded::ShardedListener;
+#[cfg(all(unix, not(target_vendor = "apple"), not(target_os = "openbsd")))]
+pub use signal::SigSet;
+#[cfg(feature = "tokio")]
+pub use tokio_interop::IntoTokio;
+#[cfg(unix)]
+pub use unix_builder::{BoundUnixListener, UnixListenerBuilder};
+#[cfg(all(feature = "io-uring", any(target_os = "linux", target_os = "android")))]
+pub use uring::UringPoller;
+
+/// The raw operating system handle that is handed to the poll function of the operating system.
+#[cfg(unix)]
+pub type RawHandle = std::os::fd::RawFd;
+
+/// The raw operating system handle that is handed to the poll function of the operating system.
+#[cfg(windows)]
+pub type RawHandle = std::os::windows::io::RawSocket;
+
+/// Backend that is used on the current target.
+#[cfg(any(target_vendor = "apple", target_os = "openbsd"))]
+use crate::unix_poll as sys;
 
-/// extension Trait for `TcpListener` and `UnixListener`
+/// Backend that is used on the current target.
+#[cfg(all(unix, not(target_vendor = "apple"), not(target_os = "openbsd")))]
+use crate::unix_ppoll as sys;
+
+/// Backend that is used on the current target.
+#[cfg(windows)]
+use crate::windows as sys;
+
+/// extension Trait for `TcpListener`, `UnixListener` and their streams, see `FdPoller` for other handles.
 pub trait PollEx {
+    /// Returns the raw operating system handle of the listener.
+    /// This handle is passed to the poll function of the operating system.
+    fn raw_handle(&self) -> RawHandle;
+
+    /// Returns what a poll of this handle waits for.
+    /// Listeners wait until they are readable which means that `accept` does not block.
+    fn interest(&self) -> Interest {
+        Interest::READ
+    }
+
     /// This function returns Ok(true) if a later call to `accept` returns a stream or error without blocking.
     ///
     /// Note: If this function returns Ok(true) and another thread calls `accept` before this thread
@@ -98,74 +210,359 @@ pub trait PollEx {
     /// # Errors
     /// Operating system and implementation-specific errors.
     ///
-    fn poll(&self, timeout: Option<Duration>) -> io::Result<bool>;
+    fn poll(&self, timeout: Option<Duration>) -> io::Result<bool> {
+        let mut fds = [sys::new_poll_fd(self.raw_handle(), self.interest())?];
+        Ok(Backend::native().poll_fds(&mut fds, timeout)? != 0)
+    }
+
+    /// This function returns the readiness flags the operating system reported for the listener.
+    ///
+    /// An empty set is returned if the timeout elapses or an operating system dependent spurious wakeup occurs.
+    /// Unlike `poll` this allows to tell a listener that is ready to `accept` apart from one
+    /// that is in an error state or was closed.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    fn poll_events(&self, timeout: Option<Duration>) -> io::Result<Readiness> {
+        self.poll_interest(self.interest(), timeout)
+    }
+
+    /// This function returns the readiness flags the operating system reported for the handle
+    /// while waiting for the given interest instead of the interest of the handle.
+    ///
+    /// An empty set is returned if the timeout elapses or an operating system dependent spurious wakeup occurs.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    fn poll_interest(&self, interest: Interest, timeout: Option<Duration>) -> io::Result<Readiness> {
+        let mut fds = [sys::new_poll_fd(self.raw_handle(), interest)?];
+        if Backend::native().poll_fds(&mut fds, timeout)? == 0 {
+            return Ok(Readiness::EMPTY);
+        }
+
+        Ok(sys::readiness(fds[0]))
+    }
+
+    /// This function returns Ok(true) if a later call to `write` writes data or returns an error without blocking.
+    ///
+    /// For a non-blocking `connect` this means that the connection attempt has completed, successfully or not.
+    ///
+    /// This function will return Ok(false) if the timeout elapses
+    /// or an operating system dependent spurious wakeup occurs.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    fn poll_writable(&self, timeout: Option<Duration>) -> io::Result<bool> {
+        Ok(!self.poll_interest(Interest::WRITE, timeout)?.is_empty())
+    }
+
+    /// This function returns Ok(true) if a later call to `accept` returns a stream or error without blocking.
+    ///
+    /// Unlike `poll` this function only returns Ok(false) once the deadline has passed.
+    /// Spurious wakeups and interruptions by signals (EINTR) are handled by polling again with the remaining time.
+    ///
+    /// Note: The same race as described in `poll` applies if this function returns Ok(true).
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    fn poll_deadline(&self, deadline: Instant) -> io::Result<bool> {
+        loop {
+            match self.poll(Some(deadline.saturating_duration_since(Instant::now()))) {
+                Ok(true) => return Ok(true),
+                Ok(false) => {}
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+
+            if Instant::now() >= deadline {
+                return Ok(false);
+            }
+        }
+    }
+
+    /// This function returns Ok(true) if a later call to `accept` returns a stream or error without blocking.
+    ///
+    /// Unlike `poll` this function only returns Ok(false) once the full timeout has elapsed.
+    /// See `poll_deadline`.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    fn poll_exact(&self, timeout: Duration) -> io::Result<bool> {
+        let Some(deadline) = Instant::now().checked_add(timeout) else {
+            //The deadline does not fit into an Instant, this is as good as forever.
+            self.poll_until_ready()?;
+            return Ok(true);
+        };
+
+        self.poll_deadline(deadline)
+    }
+
+    /// This function returns Ok(true) if a later call to `accept` returns a stream or error without blocking.
+    ///
+    /// Behaves like `poll`, except that an interruption by a signal (EINTR) is handled as specified by the policy.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    /// An `Interrupted` error is only returned with `EintrPolicy::Propagate`.
+    ///
+    fn poll_with_policy(&self, timeout: Option<Duration>, policy: EintrPolicy) -> io::Result<bool> {
+        poll_with_policy(timeout, policy, |timeout| self.poll(timeout))
+    }
+
+    /// This function returns Ok(true) if a later call to `accept` returns a stream or error without blocking.
+    ///
+    /// Behaves like `poll_with_policy` with the timeout and policy of the options,
+    /// the poll is done by the backend of the options.
+    ///
+    /// # Errors
+    /// `Unsupported` if the backend is not available on the target.
+    /// Operating system and implementation-specific errors.
+    ///
+    fn poll_with_options(&self, options: &PollOptions) -> io::Result<bool> {
+        let backend = options.get_backend();
+        if backend == Backend::native() {
+            return self.poll_with_policy(options.get_timeout(), options.get_eintr_policy());
+        }
+
+        let fd = sys::new_poll_fd(self.raw_handle(), self.interest())?;
+        poll_with_policy(options.get_timeout(), options.get_eintr_policy(), |timeout| {
+            Ok(backend.poll_fds(&mut [fd], timeout)? != 0)
+        })
+    }
+
+    /// This function returns Ok(true) if a later call to `accept` returns a stream or error without blocking.
+    ///
+    /// The signal mask of the calling thread is atomically replaced by the given mask for the duration of the poll.
+    /// This allows signals to be blocked normally and only be delivered while waiting for connections,
+    /// without the race between checking a flag set by the signal handler and calling poll.
+    /// If a signal is delivered the handler runs and this function returns an `Interrupted` error.
+    ///
+    /// Only available where the ppoll function exists, this always uses ppoll even if another backend is selected.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    #[cfg(all(unix, not(target_vendor = "apple"), not(target_os = "openbsd")))]
+    fn poll_with_sigmask(&self, timeout: Option<Duration>, sigmask: &SigSet) -> io::Result<bool> {
+        let mut fds = [sys::new_poll_fd(self.raw_handle(), self.interest())?];
+        Ok(sys::poll_fds_with_sigmask(&mut fds, timeout, sigmask.as_ptr())? != 0)
+    }
+
+    /// This function returns `PollOutcome::Ready` if a later call to `accept` returns a stream or error without blocking.
+    ///
+    /// This function returns `PollOutcome::Interrupted` as soon as the interrupt is triggered,
+    /// even if the listener is ready at the same time.
+    ///
+    /// This function will return `PollOutcome::TimedOut` if the timeout elapses
+    /// or an operating system dependent spurious wakeup occurs.
+    /// This function does not guarantee that the full timeout has elapsed when it returns `PollOutcome::TimedOut`.
+    ///
+    /// Note: The same race as described in `poll` applies if this function returns `PollOutcome::Ready`.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    fn poll_interruptible(&self, timeout: Option<Duration>, interrupt: &PollInterrupt) -> io::Result<PollOutcome> {
+        let mut fds = [
+            sys::new_poll_fd(self.raw_handle(), self.interest())?,
+            sys::new_poll_fd(interrupt.raw_handle(), Interest::READ)?,
+        ];
+
+        if Backend::native().poll_fds(&mut fds, timeout)? == 0 {
+            return Ok(PollOutcome::TimedOut);
+        }
+
+        if sys::is_ready(fds[1]) {
+            return Ok(PollOutcome::Interrupted);
+        }
+
+        if sys::is_ready(fds[0]) {
+            return Ok(PollOutcome::Ready);
+        }
+
+        Ok(PollOutcome::TimedOut)
+    }
+
+    /// This function will block until a later call to `accept` returns a stream or error without blocking
+    /// or until the interrupt is triggered.
+    ///
+    /// This function ignores any spurious wakeup, it never returns `PollOutcome::TimedOut`.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    fn poll_until_ready_interruptible(&self, interrupt: &PollInterrupt) -> io::Result<PollOutcome> {
+        loop {
+            let outcome = self.poll_interruptible(None, interrupt)?;
+            if outcome != PollOutcome::TimedOut {
+                return Ok(outcome);
+            }
+        }
+    }
+}
+
+/// Calls the poll function with the remaining time until it returns something other than EINTR
+/// or the policy says otherwise.
+fn poll_with_policy(
+    timeout: Option<Duration>,
+    policy: EintrPolicy,
+    mut poll: impl FnMut(Option<Duration>) -> io::Result<bool>,
+) -> io::Result<bool> {
+    let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+    loop {
+        let err = match poll(remaining(timeout, deadline)) {
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => err,
+            other => return other,
+        };
+
+        match policy {
+            EintrPolicy::Retry => {}
+            EintrPolicy::ReturnFalse => return Ok(false),
+            EintrPolicy::Propagate => return Err(err),
+        }
+    }
+}
+
+/// Returns the time that is left until the deadline of a timeout.
+/// A deadline of None with a timeout of Some means that the deadline does not fit into an Instant,
+/// this is as good as forever.
+fn remaining(timeout: Option<Duration>, deadline: Option<Instant>) -> Option<Duration> {
+    match (timeout, deadline) {
+        (Some(_), Some(deadline)) => Some(deadline.saturating_duration_since(Instant::now())),
+        (Some(_), None) | (None, _) => None,
+    }
+}
+
+/// Converts a timeout to milliseconds for poll functions with millisecond resolution.
+/// Sub millisecond fractions are rounded up so that the poll never returns before the timeout elapsed.
+#[cfg(any(unix, windows))]
+fn millis_rounded_up(timeout: Duration) -> u128 {
+    timeout.as_millis() + u128::from(timeout.subsec_nanos() % 1_000_000 != 0)
 }
 
 /// Unix libc specific impl using poll.
-/// Apple and openbsd do not have the "ppoll" function and must therefore use this impl.
-#[cfg(any(target_vendor = "apple", target_os = "openbsd"))]
+/// Apple and openbsd do not have the "ppoll" function and must therefore use this impl,
+/// other unix targets only use it if `Backend::Poll` is selected.
+#[cfg(unix)]
 mod unix_poll {
-    use crate::PollEx;
-    use libc::{c_int, poll, pollfd, POLLIN};
+    use crate::{Interest, PollEx, RawHandle, Readiness};
+    use libc::{c_int, fcntl, nfds_t, poll, pollfd, F_GETFL, O_NONBLOCK, POLLERR, POLLHUP, POLLIN, POLLNVAL, POLLOUT, POLLPRI};
     use std::io;
-    use std::net::TcpListener;
+    use std::net::{TcpListener, TcpStream};
     use std::os::fd::AsRawFd;
-    use std::os::fd::RawFd;
     use std::time::Duration;
 
-    /// apple poll impl is the same for tcp and unix sockets.
-    fn poll_impl_apple(fd: RawFd, timeout: Option<Duration>) -> io::Result<bool> {
-        const MAX_TIMEOUT_PER_CALL: u128 = c_int::MAX as u128;
+    /// The structure that is handed to poll for each handle.
+    pub type PollFd = pollfd;
+
+    /// Creates the poll structure for a handle.
+    //Signature must match the windows impl where this conversion can fail.
+    #[allow(clippy::unnecessary_wraps)]
+    pub const fn new_poll_fd(fd: RawHandle, interest: Interest) -> io::Result<PollFd> {
+        let mut events = 0;
+        if interest.is_readable() {
+            events |= POLLIN;
+        }
 
-        let mut fd = Box::pin(pollfd {
+        if interest.is_writable() {
+            events |= POLLOUT;
+        }
+
+        Ok(pollfd {
             fd,
-            events: POLLIN,
+            events,
             revents: 0,
-        });
+        })
+    }
 
-        let Some(mut ms) = timeout.map(|a| a.as_millis()) else {
-            let count = unsafe { poll(fd.as_mut().get_mut(), 1, -1) };
-            if count < 0 {
-                return Err(io::Error::last_os_error());
+    /// Returns true if poll reported any event for the handle.
+    pub const fn is_ready(fd: PollFd) -> bool {
+        fd.revents != 0
+    }
+
+    /// Converts the events reported by poll into readiness flags.
+    pub fn readiness(fd: PollFd) -> Readiness {
+        let mut readiness = Readiness::EMPTY;
+        for (event, flag) in [
+            (POLLIN, Readiness::READABLE),
+            (POLLOUT, Readiness::WRITABLE),
+            (POLLPRI, Readiness::PRIORITY),
+            (POLLERR, Readiness::ERROR),
+            (POLLHUP, Readiness::HANGUP),
+            (POLLNVAL, Readiness::INVALID),
+        ] {
+            if fd.revents & event != 0 {
+                readiness |= flag;
             }
+        }
+
+        readiness
+    }
+
+    /// Returns true if `O_NONBLOCK` is set on the handle.
+    pub fn is_nonblocking(fd: RawHandle) -> io::Result<bool> {
+        let flags = unsafe { fcntl(fd, F_GETFL) };
+        if flags < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(flags & O_NONBLOCK != 0)
+    }
 
-            return Ok(count != 0);
+    /// unix poll impl is the same for tcp and unix sockets.
+    /// Returns the amount of handles that are ready.
+    pub fn poll_fds(fds: &mut [PollFd], timeout: Option<Duration>) -> io::Result<usize> {
+        const MAX_TIMEOUT_PER_CALL: u128 = c_int::MAX as u128;
+
+        let nfds = nfds_t::try_from(fds.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many handles to poll"))?;
+
+        let Some(mut ms) = timeout.map(crate::millis_rounded_up) else {
+            let count = unsafe { poll(fds.as_mut_ptr(), nfds, -1) };
+            return usize::try_from(count).map_err(|_| io::Error::last_os_error());
         };
 
         while ms > MAX_TIMEOUT_PER_CALL {
             ms -= MAX_TIMEOUT_PER_CALL;
-            let count = unsafe { poll(fd.as_mut().get_mut(), 1, c_int::MAX) };
-
-            if count < 0 {
-                return Err(io::Error::last_os_error());
-            }
+            let count = unsafe { poll(fds.as_mut_ptr(), nfds, c_int::MAX) };
+            let count = usize::try_from(count).map_err(|_| io::Error::last_os_error())?;
 
             if count != 0 {
-                return Ok(true);
+                return Ok(count);
             }
         }
 
 
-        let count = unsafe { poll(fd.as_mut().get_mut(), 1, c_int::try_from(ms).expect("Unreachable: a conversion from u128 to c_int failed even tho the u128 is less than c_int::MAX")) };
+        let count = unsafe { poll(fds.as_mut_ptr(), nfds, c_int::try_from(ms).expect("Unreachable: a conversion from u128 to c_int failed even tho the u128 is less than c_int::MAX")) };
+        usize::try_from(count).map_err(|_| io::Error::last_os_error())
+    }
 
-        if count < 0 {
-            return Err(io::Error::last_os_error());
+    impl PollEx for TcpListener {
+        fn raw_handle(&self) -> RawHandle {
+            self.as_raw_fd()
         }
+    }
 
-        Ok(count != 0)
+    impl PollEx for std::os::unix::net::UnixListener {
+        fn raw_handle(&self) -> RawHandle {
+            self.as_raw_fd()
+        }
     }
 
-    #[cfg(unix)]
-    impl PollEx for TcpListener {
-        fn poll(&self, timeout: Option<Duration>) -> io::Result<bool> {
-            poll_impl_apple(self.as_raw_fd(), timeout)
+    impl PollEx for TcpStream {
+        fn raw_handle(&self) -> RawHandle {
+            self.as_raw_fd()
         }
     }
 
-    #[cfg(unix)]
-    impl PollEx for std::os::unix::net::UnixListener {
-        fn poll(&self, timeout: Option<Duration>) -> io::Result<bool> {
-            poll_impl_apple(self.as_raw_fd(), timeout)
+    impl PollEx for std::os::unix::net::UnixStream {
+        fn raw_handle(&self) -> RawHandle {
+            self.as_raw_fd()
         }
     }
 }
@@ -174,35 +571,18 @@ mod unix_poll {
 /// Apple and openbsd do not have ppoll.
 #[cfg(all(unix, not(target_vendor = "apple"), not(target_os = "openbsd")))]
 mod unix_ppoll {
-    use crate::PollEx;
-    use libc::{pollfd, ppoll, timespec, POLLIN};
+    use libc::{nfds_t, ppoll, sigset_t, timespec};
     use std::io;
-    use std::net::TcpListener;
-    use std::os::fd::AsRawFd;
-    use std::os::fd::RawFd;
     use std::ptr::null;
     use std::time::Duration;
 
-    /// unix poll impl is the same for tcp and unix sockets.
-    fn poll_impl_unix(fd: RawFd, timeout: Option<Duration>) -> io::Result<bool> {
-        let mut fd = Box::pin(pollfd {
-            fd,
-            events: POLLIN,
-            revents: 0,
-        });
-
-        let Some(timeout) = timeout else {
-            let count = unsafe { ppoll(fd.as_mut().get_mut(), 1, null(), null()) };
-            if count < 0 {
-                return Err(io::Error::last_os_error());
-            }
-
-            return Ok(count != 0);
-        };
+    pub use crate::unix_poll::{is_nonblocking, is_ready, new_poll_fd, readiness, PollFd};
 
+    /// Converts a timeout into a timespec.
+    pub fn to_timespec(timeout: Duration) -> io::Result<timespec> {
         //This depends on the target and libc that is used!
         #[allow(clippy::unnecessary_fallible_conversions)]
-        let time = Box::pin(timespec {
+        Ok(timespec {
             tv_sec: timeout.as_secs().try_into().map_err(|_| {
                 io::Error::new(
                     io::ErrorKind::InvalidInput,
@@ -215,102 +595,313 @@ mod unix_ppoll {
                     "timeout subsec_nanos is too large to fit into libc::timespec.tv_nsec",
                 )
             })?,
-        });
+        })
+    }
+
+    /// unix poll impl is the same for tcp and unix sockets.
+    /// Returns the amount of handles that are ready.
+    pub fn poll_fds(fds: &mut [PollFd], timeout: Option<Duration>) -> io::Result<usize> {
+        poll_fds_with_sigmask(fds, timeout, null())
+    }
+
+    /// Like `poll_fds` but replaces the signal mask of the thread during the call.
+    /// A null sigmask leaves the signal mask of the thread unchanged.
+    pub fn poll_fds_with_sigmask(fds: &mut [PollFd], timeout: Option<Duration>, sigmask: *const sigset_t) -> io::Result<usize> {
+        //This depends on the target and libc that is used!
+        #[allow(clippy::unnecessary_fallible_conversions)]
+        let nfds = nfds_t::try_from(fds.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many handles to poll"))?;
+
+        let Some(timeout) = timeout else {
+            let count = unsafe { ppoll(fds.as_mut_ptr(), nfds, null(), sigmask) };
+            return usize::try_from(count).map_err(|_| io::Error::last_os_error());
+        };
+
+        let time = Box::pin(to_timespec(timeout)?);
+
+        let count = unsafe { ppoll(fds.as_mut_ptr(), nfds, time.as_ref().get_ref(), sigmask) };
+        usize::try_from(count).map_err(|_| io::Error::last_os_error())
+    }
+}
+
+/// Unix libc specific impl using select, for sandboxes that only allow select.
+/// select can only watch handles below `FD_SETSIZE`.
+#[cfg(unix)]
+mod unix_select {
+    use crate::unix_poll::PollFd;
+    use libc::{c_int, fd_set, select, time_t, timeval, FD_ISSET, FD_SET, FD_ZERO, POLLIN, POLLNVAL, POLLOUT, POLLPRI};
+    use std::io;
+    use std::mem::MaybeUninit;
+    use std::ptr::null_mut;
+    use std::time::Duration;
+
+    /// The amount of handles an `fd_set` can hold.
+    //The type of FD_SETSIZE depends on the target.
+    #[allow(clippy::unnecessary_cast, clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    const FD_SETSIZE: usize = libc::FD_SETSIZE as usize;
+
+    /// Some systems reject select timeouts above 100 million seconds, longer timeouts are split into several calls.
+    const MAX_SECS_PER_CALL: u64 = 100_000_000;
+
+    /// Returns an empty `fd_set`.
+    fn empty_set() -> fd_set {
+        let mut set = MaybeUninit::<fd_set>::uninit();
+        unsafe {
+            FD_ZERO(set.as_mut_ptr());
+            set.assume_init()
+        }
+    }
+
+    /// Converts a timeout into a timeval, sub microsecond fractions are rounded up.
+    fn to_timeval(timeout: Duration) -> io::Result<timeval> {
+        let mut secs = timeout.as_secs();
+        let mut micros = timeout.subsec_micros() + u32::from(timeout.subsec_nanos() % 1_000 != 0);
+        if micros == 1_000_000 {
+            secs += 1;
+            micros = 0;
+        }
+
+        //This depends on the target and libc that is used!
+        #[allow(clippy::unnecessary_fallible_conversions)]
+        Ok(timeval {
+            tv_sec: time_t::try_from(secs)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "timeout duration is too large to fit into libc::timeval.tv_sec"))?,
+            tv_usec: micros
+                .try_into()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "timeout subsec_micros is too large to fit into libc::timeval.tv_usec"))?,
+        })
+    }
+
+    /// Calls select once and stores the result in the poll structures.
+    /// Returns the amount of handles that are ready.
+    fn select_once(fds: &mut [PollFd], timeout: Option<Duration>) -> io::Result<usize> {
+        let mut read = empty_set();
+        let mut write = empty_set();
+        let mut except = empty_set();
+        let mut nfds: c_int = 0;
+        for fd in fds.iter_mut() {
+            fd.revents = 0;
+            //poll ignores negative handles, select does the same.
+            let Ok(index) = usize::try_from(fd.fd) else {
+                continue;
+            };
+
+            if index >= FD_SETSIZE {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "handle is too large for select, it must be below FD_SETSIZE"));
+            }
+
+            unsafe {
+                if fd.events & POLLIN != 0 {
+                    FD_SET(fd.fd, &mut read);
+                }
+
+                if fd.events & POLLOUT != 0 {
+                    FD_SET(fd.fd, &mut write);
+                }
 
-        let count = unsafe { ppoll(fd.as_mut().get_mut(), 1, time.as_ref().get_ref(), null()) };
+                FD_SET(fd.fd, &mut except);
+            }
+
+            nfds = nfds.max(fd.fd + 1);
+        }
+
+        let mut time = timeout.map(to_timeval).transpose()?;
+        let time_ptr = time.as_mut().map_or(null_mut(), |time| time as *mut timeval);
+        let count = unsafe { select(nfds, &mut read, &mut write, &mut except, time_ptr) };
         if count < 0 {
-            return Err(io::Error::last_os_error());
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::EBADF) {
+                return Ok(mark_invalid(fds));
+            }
+
+            return Err(err);
+        }
+
+        if count == 0 {
+            return Ok(0);
+        }
+
+        let mut ready = 0;
+        for fd in fds.iter_mut().filter(|fd| fd.fd >= 0) {
+            unsafe {
+                if FD_ISSET(fd.fd, &read) {
+                    fd.revents |= POLLIN;
+                }
+
+                if FD_ISSET(fd.fd, &write) {
+                    fd.revents |= POLLOUT;
+                }
+
+                if FD_ISSET(fd.fd, &except) {
+                    fd.revents |= POLLPRI;
+                }
+            }
+
+            ready += usize::from(fd.revents != 0);
         }
 
-        Ok(count != 0)
+        Ok(ready)
     }
 
-    #[cfg(unix)]
-    impl PollEx for TcpListener {
-        fn poll(&self, timeout: Option<Duration>) -> io::Result<bool> {
-            poll_impl_unix(self.as_raw_fd(), timeout)
+    /// select fails with EBADF if any handle is not open, poll reports `POLLNVAL` for those handles instead.
+    /// Returns the amount of handles that are not open.
+    fn mark_invalid(fds: &mut [PollFd]) -> usize {
+        let mut invalid = 0;
+        for fd in fds.iter_mut().filter(|fd| fd.fd >= 0) {
+            if unsafe { libc::fcntl(fd.fd, libc::F_GETFD) } < 0 {
+                fd.revents = POLLNVAL;
+                invalid += 1;
+            }
         }
+
+        invalid
     }
 
-    #[cfg(unix)]
-    impl PollEx for std::os::unix::net::UnixListener {
-        fn poll(&self, timeout: Option<Duration>) -> io::Result<bool> {
-            poll_impl_unix(self.as_raw_fd(), timeout)
+    /// select impl is the same for tcp and unix sockets.
+    /// Returns the amount of handles that are ready.
+    pub fn poll_fds(fds: &mut [PollFd], timeout: Option<Duration>) -> io::Result<usize> {
+        let Some(mut timeout) = timeout else {
+            return select_once(fds, None);
+        };
+
+        let chunk = Duration::from_secs(MAX_SECS_PER_CALL);
+        while timeout > chunk {
+            timeout -= chunk;
+            let count = select_once(fds, Some(chunk))?;
+            if count != 0 {
+                return Ok(count);
+            }
         }
+
+        select_once(fds, Some(timeout))
     }
 }
 
 /// Windows-specific impl
 #[cfg(windows)]
 mod windows {
-    use crate::PollEx;
+    use crate::{Interest, PollEx, RawHandle, Readiness};
     use std::io;
-    use std::net::TcpListener;
+    use std::net::{TcpListener, TcpStream};
     use std::os::windows::io::AsRawSocket;
     use std::time::Duration;
     use windows_sys::Win32::Networking::WinSock::{
-        WSAGetLastError, WSAPoll, POLLRDNORM, SOCKET_ERROR, WSAPOLLFD,
+        WSAGetLastError, WSAPoll, POLLERR, POLLHUP, POLLNVAL, POLLPRI, POLLRDBAND, POLLRDNORM, POLLWRNORM, SOCKET, SOCKET_ERROR, WSAPOLLFD,
     };
 
-    impl PollEx for TcpListener {
-        fn poll(&self, timeout: Option<Duration>) -> io::Result<bool> {
-            const MAX_TIMEOUT_PER_CALL: u128 = i32::MAX as u128;
-
-            let windows_sock_handle = windows_sys::Win32::Networking::WinSock::SOCKET::try_from(self.as_raw_socket())
-                //Unreachable unless the stdlib or windows-sys or both fucked up!
-                .map_err(|_| io::Error::new(io::ErrorKind::Other, "as_raw_socket handle does not fit into windows_sys::Win32::Networking::WinSock::SOCKET"))?;
-
-            let mut pollfd = Box::pin(WSAPOLLFD {
-                fd: windows_sock_handle,
-                events: POLLRDNORM,
-                revents: 0,
-            });
-
-            let Some(mut ms) = timeout.map(|a| a.as_millis()) else {
-                let result = unsafe {
-                    //https://learn.microsoft.com/en-us/windows/win32/api/winsock2/nf-winsock2-wsapoll
-                    WSAPoll(pollfd.as_mut().get_mut(), 1, -1)
-                };
-
-                if result == SOCKET_ERROR {
-                    unsafe {
-                        return Err(io::Error::from_raw_os_error(WSAGetLastError()));
-                    }
-                }
+    /// The structure that is handed to `WSAPoll` for each handle.
+    pub type PollFd = WSAPOLLFD;
 
-                return Ok(result != 0);
-            };
+    /// Creates the poll structure for a handle.
+    pub fn new_poll_fd(handle: RawHandle, interest: Interest) -> io::Result<PollFd> {
+        let windows_sock_handle = SOCKET::try_from(handle)
+            //Unreachable unless the stdlib or windows-sys or both fucked up!
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "as_raw_socket handle does not fit into windows_sys::Win32::Networking::WinSock::SOCKET"))?;
 
-            while ms > MAX_TIMEOUT_PER_CALL {
-                ms -= MAX_TIMEOUT_PER_CALL;
-                let result = unsafe {
-                    //https://learn.microsoft.com/en-us/windows/win32/api/winsock2/nf-winsock2-wsapoll
-                    WSAPoll(pollfd.as_mut().get_mut(), 1, i32::MAX)
-                };
-
-                if result == SOCKET_ERROR {
-                    unsafe {
-                        return Err(io::Error::from_raw_os_error(WSAGetLastError()));
-                    }
-                }
+        let mut events = 0;
+        if interest.is_readable() {
+            events |= POLLRDNORM;
+        }
 
-                if result != 0 {
-                    return Ok(true);
-                }
+        if interest.is_writable() {
+            events |= POLLWRNORM;
+        }
+
+        Ok(WSAPOLLFD {
+            fd: windows_sock_handle,
+            events,
+            revents: 0,
+        })
+    }
+
+    /// Returns true if `WSAPoll` reported any event for the handle.
+    pub const fn is_ready(fd: PollFd) -> bool {
+        fd.revents != 0
+    }
+
+    /// Converts the events reported by poll into readiness flags.
+    pub fn readiness(fd: PollFd) -> Readiness {
+        let mut readiness = Readiness::EMPTY;
+        for (event, flag) in [
+            (POLLRDNORM | POLLRDBAND, Readiness::READABLE),
+            (POLLWRNORM, Readiness::WRITABLE),
+            (POLLPRI, Readiness::PRIORITY),
+            (POLLERR, Readiness::ERROR),
+            (POLLHUP, Readiness::HANGUP),
+            (POLLNVAL, Readiness::INVALID),
+        ] {
+            if fd.revents & event != 0 {
+                readiness |= flag;
+            }
+        }
+
+        readiness
+    }
+
+    /// Windows cannot query if a socket is non-blocking, sockets are blocking unless changed by the user.
+    //Signature must match the unix impl where this query can fail.
+    #[allow(clippy::unnecessary_wraps)]
+    pub const fn is_nonblocking(_handle: RawHandle) -> io::Result<bool> {
+        Ok(false)
+    }
+
+    /// Converts the result of `WSAPoll` into the amount of ready handles.
+    fn poll_result(result: i32) -> io::Result<usize> {
+        if result == SOCKET_ERROR {
+            unsafe {
+                return Err(io::Error::from_raw_os_error(WSAGetLastError()));
             }
+        }
+
+        usize::try_from(result).map_err(|_| io::Error::new(io::ErrorKind::Other, "WSAPoll returned a negative count"))
+    }
 
+    /// Returns the amount of handles that are ready.
+    pub fn poll_fds(fds: &mut [PollFd], timeout: Option<Duration>) -> io::Result<usize> {
+        const MAX_TIMEOUT_PER_CALL: u128 = i32::MAX as u128;
+
+        let nfds = u32::try_from(fds.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many handles to poll"))?;
+
+        let Some(mut ms) = timeout.map(crate::millis_rounded_up) else {
             let result = unsafe {
                 //https://learn.microsoft.com/en-us/windows/win32/api/winsock2/nf-winsock2-wsapoll
-                WSAPoll(pollfd.as_mut().get_mut(), 1, i32::try_from(ms).expect("Unreachable: a conversion from u128 to i32 failed even tho the u128 is less than i32::MAX"))
+                WSAPoll(fds.as_mut_ptr(), nfds, -1)
             };
 
-            if result == SOCKET_ERROR {
-                unsafe {
-                    return Err(io::Error::from_raw_os_error(WSAGetLastError()));
-                }
+            return poll_result(result);
+        };
+
+        while ms > MAX_TIMEOUT_PER_CALL {
+            ms -= MAX_TIMEOUT_PER_CALL;
+            let result = unsafe {
+                //https://learn.microsoft.com/en-us/windows/win32/api/winsock2/nf-winsock2-wsapoll
+                WSAPoll(fds.as_mut_ptr(), nfds, i32::MAX)
+            };
+
+            let count = poll_result(result)?;
+            if count != 0 {
+                return Ok(count);
             }
+        }
+
+        let result = unsafe {
+            //https://learn.microsoft.com/en-us/windows/win32/api/winsock2/nf-winsock2-wsapoll
+            WSAPoll(fds.as_mut_ptr(), nfds, i32::try_from(ms).expect("Unreachable: a conversion from u128 to i32 failed even tho the u128 is less than i32::MAX"))
+        };
+
+        poll_result(result)
+    }
+
+    impl PollEx for TcpListener {
+        fn raw_handle(&self) -> RawHandle {
+            self.as_raw_socket()
+        }
+    }
 
-            Ok(result != 0)
+    impl PollEx for TcpStream {
+        fn raw_handle(&self) -> RawHandle {
+            self.as_raw_socket()
         }
     }
 }
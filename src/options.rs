@@ -0,0 +1,93 @@
+//! Options that control how a poll behaves.
+
+use crate::Backend;
+use std::time::Duration;
+
+/// What a poll does when it is interrupted by a signal (EINTR).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum EintrPolicy {
+    /// Poll again with the remaining time.
+    Retry,
+    /// Return Ok(false) as if the timeout elapsed.
+    ReturnFalse,
+    /// Return the `Interrupted` error, this is what `PollEx::poll` does.
+    #[default]
+    Propagate,
+}
+
+/// Options for `PollEx::poll_with_options`.
+///
+/// ## Example
+/// ```rust
+/// use std::net::TcpListener;
+/// use std::time::Duration;
+/// use listener_poll::{EintrPolicy, PollEx, PollOptions};
+///
+/// let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+/// let options = PollOptions::new()
+///     .timeout(Some(Duration::from_millis(10)))
+///     .eintr_policy(EintrPolicy::Retry);
+///
+/// assert!(!listener.poll_with_options(&options).unwrap());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct PollOptions {
+    /// the timeout, None waits forever.
+    timeout: Option<Duration>,
+    /// what to do on EINTR.
+    eintr_policy: EintrPolicy,
+    /// the operating system function that polls.
+    backend: Backend,
+}
+
+impl PollOptions {
+    /// Creates options that wait forever, propagate EINTR and use `Backend::native`.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            timeout: None,
+            eintr_policy: EintrPolicy::Propagate,
+            backend: Backend::native(),
+        }
+    }
+
+    /// Sets the timeout, None waits forever.
+    #[must_use]
+    pub const fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets what to do when the poll is interrupted by a signal.
+    #[must_use]
+    pub const fn eintr_policy(mut self, policy: EintrPolicy) -> Self {
+        self.eintr_policy = policy;
+        self
+    }
+
+    /// Sets the operating system function that polls.
+    /// Polling fails with `Unsupported` if the backend is not available on the target.
+    #[must_use]
+    pub const fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Returns the timeout.
+    #[must_use]
+    pub const fn get_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Returns what to do when the poll is interrupted by a signal.
+    #[must_use]
+    pub const fn get_eintr_policy(&self) -> EintrPolicy {
+        self.eintr_policy
+    }
+
+    /// Returns the operating system function that polls.
+    #[must_use]
+    pub const fn get_backend(&self) -> Backend {
+        self.backend
+    }
+}
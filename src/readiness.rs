@@ -0,0 +1,120 @@
+//! Detailed readiness reported by a poll.
+
+use std::fmt;
+use std::ops::{BitAnd, BitOr, BitOrAssign};
+
+/// Set of readiness flags reported by the operating system for a polled handle.
+///
+/// An empty set means that the poll timed out.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Readiness(u8);
+
+impl Readiness {
+    /// No flag is set.
+    pub const EMPTY: Self = Self(0);
+    /// A later call to `accept` or `read` does not block (`POLLIN`).
+    pub const READABLE: Self = Self(1);
+    /// A later call to `write` does not block (`POLLOUT`).
+    pub const WRITABLE: Self = Self(1 << 1);
+    /// Priority data can be read (`POLLPRI`).
+    pub const PRIORITY: Self = Self(1 << 2);
+    /// An error is pending on the handle (`POLLERR`).
+    pub const ERROR: Self = Self(1 << 3);
+    /// The peer hung up (`POLLHUP`).
+    pub const HANGUP: Self = Self(1 << 4);
+    /// The handle is not open, for example because it was closed (`POLLNVAL`).
+    pub const INVALID: Self = Self(1 << 5);
+
+    /// Returns the raw bits of the set.
+    #[must_use]
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Returns true if no flag is set.
+    #[must_use]
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns true if all flags of other are set.
+    #[must_use]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns true if any flag of other is set.
+    #[must_use]
+    pub const fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// Returns true if `READABLE` is set.
+    #[must_use]
+    pub const fn is_readable(self) -> bool {
+        self.contains(Self::READABLE)
+    }
+
+    /// Returns true if `WRITABLE` is set.
+    #[must_use]
+    pub const fn is_writable(self) -> bool {
+        self.contains(Self::WRITABLE)
+    }
+
+    /// Returns true if `ERROR` is set.
+    #[must_use]
+    pub const fn is_error(self) -> bool {
+        self.contains(Self::ERROR)
+    }
+
+    /// Returns true if `HANGUP` is set.
+    #[must_use]
+    pub const fn is_hangup(self) -> bool {
+        self.contains(Self::HANGUP)
+    }
+
+    /// Returns true if `INVALID` is set.
+    #[must_use]
+    pub const fn is_invalid(self) -> bool {
+        self.contains(Self::INVALID)
+    }
+}
+
+impl BitOr for Readiness {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Readiness {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl BitAnd for Readiness {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl fmt::Debug for Readiness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = [
+            (Self::READABLE, "READABLE"),
+            (Self::WRITABLE, "WRITABLE"),
+            (Self::PRIORITY, "PRIORITY"),
+            (Self::ERROR, "ERROR"),
+            (Self::HANGUP, "HANGUP"),
+            (Self::INVALID, "INVALID"),
+        ];
+
+        f.debug_set()
+            .entries(names.iter().filter(|(flag, _)| self.contains(*flag)).map(|(_, name)| name))
+            .finish()
+    }
+}
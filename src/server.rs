@@ -0,0 +1,593 @@
+//! Accept loop that dispatches connections to a pool of worker threads.
+
+use crate::{ListenerSet, PollAccept, PollEx, PollInterrupt, RawHandle};
+use std::io;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Builder for a server that accepts connections on a dedicated thread and handles them on a pool of worker threads.
+///
+/// The accept thread polls all listeners together and accepts every ready connection.
+/// Connections are queued in a bounded queue that the workers take them from, once the queue is full
+/// the accept thread stops polling the listeners until a worker is free, so the kernel backlog absorbs load spikes.
+/// Meanwhile it keeps waiting for `shutdown`, a busy pool does not delay stopping.
+/// `ServerHandle::shutdown` wakes the accept thread immediately, no timeout polling is involved,
+/// `ServerHandle::shutdown_graceful` additionally waits for the handlers up to a deadline.
+///
+/// The listeners are switched to non-blocking, the accepted streams are blocking.
+/// A handler that panics does not take its worker down, the panic is printed and the worker continues.
+///
+/// ## Example
+/// ```rust
+/// use std::io::Write;
+/// use std::net::{TcpListener, TcpStream};
+/// use listener_poll::AcceptLoop;
+///
+/// let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+/// let addr = listener.local_addr().unwrap();
+///
+/// let server = AcceptLoop::new(|mut stream: TcpStream, _addr| {
+///     stream.write_all(b"hello").unwrap();
+/// })
+/// .listener(listener)
+/// .workers(4)
+/// .spawn()
+/// .unwrap();
+///
+/// let _stream = TcpStream::connect(addr).unwrap();
+/// server.shutdown().unwrap();
+/// server.join().unwrap();
+/// ```
+#[derive(Debug)]
+#[must_use = "the server only runs once spawn is called"]
+pub struct AcceptLoop<L, H> {
+    /// the listeners that are polled together.
+    listeners: Vec<L>,
+    /// called on a worker thread for every connection.
+    handler: H,
+    /// the amount of worker threads.
+    workers: usize,
+    /// the amount of connections that can wait for a worker, None uses the amount of workers.
+    queue_capacity: Option<usize>,
+    /// accept the backlog of the listeners when shutting down.
+    drain_backlog: bool,
+}
+
+impl<L, H> AcceptLoop<L, H>
+where
+    L: PollAccept + Send + 'static,
+    L::Stream: Send + 'static,
+    L::Addr: Send + 'static,
+    H: Fn(L::Stream, L::Addr) + Send + Sync + 'static,
+{
+    /// Creates a builder without listeners, the amount of workers defaults to the available parallelism.
+    pub fn new(handler: H) -> Self {
+        Self {
+            listeners: Vec::new(),
+            handler,
+            workers: thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get),
+            queue_capacity: None,
+            drain_backlog: false,
+        }
+    }
+
+    /// Adds a listener, connections of all listeners are passed to the same handler.
+    pub fn listener(mut self, listener: L) -> Self {
+        self.listeners.push(listener);
+        self
+    }
+
+    /// Sets the amount of worker threads that call the handler.
+    pub const fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    /// Sets the amount of accepted connections that can wait for a free worker, defaults to the amount of workers.
+    /// A capacity of 0 hands every connection directly to a waiting worker.
+    pub const fn queue_capacity(mut self, capacity: usize) -> Self {
+        self.queue_capacity = Some(capacity);
+        self
+    }
+
+    /// Sets if the connections that are waiting in the backlog of the listeners are accepted and handled
+    /// when the server shuts down. Otherwise they are reset once the listeners are closed, which is the default.
+    pub const fn drain_backlog(mut self, drain: bool) -> Self {
+        self.drain_backlog = drain;
+        self
+    }
+
+    /// Starts the accept thread and the workers.
+    ///
+    /// # Errors
+    /// `InvalidInput` if no listener was added or the amount of workers is 0.
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn spawn(self) -> io::Result<ServerHandle> {
+        if self.listeners.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "accept loop has no listener"));
+        }
+
+        if self.workers == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "accept loop needs at least one worker"));
+        }
+
+        for listener in &self.listeners {
+            listener.set_nonblocking(true)?;
+        }
+
+        let interrupt = PollInterrupt::new()?;
+        let (sender, receiver) = sync_channel(self.queue_capacity.unwrap_or(self.workers));
+        let receiver = Arc::new(Mutex::new(receiver));
+        let handler = Arc::new(self.handler);
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                accepting: true,
+                workers: self.workers,
+                queued: 0,
+                running: 0,
+                abandoned: false,
+                waiting_for_slot: false,
+            }),
+            changed: Condvar::new(),
+            slot_freed: PollInterrupt::new()?,
+        });
+
+        let mut workers = Vec::with_capacity(self.workers);
+        for index in 0..self.workers {
+            let receiver = Arc::clone(&receiver);
+            let handler = Arc::clone(&handler);
+            let worker_shared = Arc::clone(&shared);
+            let worker = thread::Builder::new()
+                .name(format!("listener_poll worker {index}"))
+                .spawn(move || work(&receiver, &*handler, &worker_shared));
+
+            match worker {
+                Ok(worker) => workers.push(worker),
+                Err(err) => {
+                    //Dropping the sender ends the workers that were already started.
+                    drop(sender);
+                    shared.lock().workers -= self.workers - index;
+                    join_workers(workers);
+                    return Err(err);
+                }
+            }
+        }
+
+        let listeners = self.listeners;
+        let drain = self.drain_backlog;
+        let accept_interrupt = interrupt.clone();
+        let accept_shared = Arc::clone(&shared);
+        let accept = thread::Builder::new()
+            .name("listener_poll accept".to_string())
+            .spawn(move || {
+                let result = accept_loop(&listeners, &accept_interrupt, &sender, &accept_shared, drain);
+                //The listeners are closed before the state tells that accepting has stopped.
+                drop(listeners);
+                accept_shared.lock().accepting = false;
+                accept_shared.changed.notify_all();
+                result
+            });
+
+        let accept = match accept {
+            Ok(accept) => accept,
+            Err(err) => {
+                join_workers(workers);
+                return Err(err);
+            }
+        };
+
+        Ok(ServerHandle {
+            interrupt,
+            accept,
+            workers,
+            shared,
+        })
+    }
+}
+
+/// Handle to a running `AcceptLoop`.
+///
+/// Dropping the handle detaches the server, it then runs until the process exits.
+#[derive(Debug)]
+#[must_use = "dropping the handle detaches the server"]
+pub struct ServerHandle {
+    /// wakes the accept thread.
+    interrupt: PollInterrupt,
+    /// the accept thread, it returns the error that ended the loop.
+    accept: JoinHandle<io::Result<()>>,
+    /// the worker threads.
+    workers: Vec<JoinHandle<()>>,
+    /// the state that the threads share with the handle.
+    shared: Arc<Shared>,
+}
+
+/// The result of `ServerHandle::shutdown_graceful`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ShutdownReport {
+    /// the amount of handlers that were still running when the deadline expired.
+    running: usize,
+    /// the amount of accepted connections that were still waiting for a worker when the deadline expired,
+    /// without the connections in the backlog.
+    abandoned: usize,
+}
+
+impl ShutdownReport {
+    /// Returns the amount of handlers that were still running when the deadline expired.
+    /// Their worker threads are detached and keep running until the handler returns.
+    #[must_use]
+    pub const fn running(&self) -> usize {
+        self.running
+    }
+
+    /// Returns the amount of accepted connections that were still waiting for a worker when the deadline expired.
+    /// They are closed without being handled.
+    ///
+    /// Connections that were still in the backlog of the listeners are not counted, they are closed with the
+    /// listeners or, with `AcceptLoop::drain_backlog`, accepted and closed after the deadline.
+    #[must_use]
+    pub const fn abandoned(&self) -> usize {
+        self.abandoned
+    }
+
+    /// Returns true if every handler finished before the deadline.
+    #[must_use]
+    pub const fn is_complete(&self) -> bool {
+        self.running == 0 && self.abandoned == 0
+    }
+}
+
+/// State that the accept thread, the workers and the handle share.
+#[derive(Debug)]
+struct Shared {
+    /// the counters.
+    state: Mutex<State>,
+    /// notified whenever the counters change.
+    changed: Condvar,
+    /// wakes the accept thread when a worker takes a connection out of the full queue.
+    slot_freed: PollInterrupt,
+}
+
+impl Shared {
+    /// Locks the counters, a poisoned lock is harmless because every modification is completed before it can panic.
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Counters of an `AcceptLoop`.
+#[derive(Debug)]
+struct State {
+    /// true until the accept thread has stopped and closed the listeners.
+    accepting: bool,
+    /// the amount of worker threads that have not stopped yet.
+    workers: usize,
+    /// the amount of accepted connections that wait for a worker.
+    queued: usize,
+    /// the amount of handlers that are running.
+    running: usize,
+    /// set once a graceful shutdown expired, the workers then close queued connections without handling them.
+    abandoned: bool,
+    /// set while the accept thread waits for room in the full queue.
+    waiting_for_slot: bool,
+}
+
+/// Marks a worker as stopped when it is dropped, even if the worker unwinds.
+struct WorkerGuard<'a> {
+    /// the state of the server.
+    shared: &'a Shared,
+}
+
+impl Drop for WorkerGuard<'_> {
+    fn drop(&mut self) {
+        self.shared.lock().workers -= 1;
+        self.shared.changed.notify_all();
+    }
+}
+
+impl ServerHandle {
+    /// Stops accepting connections, this does not wait for the server to stop.
+    ///
+    /// The listeners are closed once the accept thread has woken up. Connections that were already accepted
+    /// are still handled, call `join` to wait for that.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn shutdown(&self) -> io::Result<()> {
+        self.interrupt.interrupt()
+    }
+
+    /// Returns true if the accept thread has stopped, either because of `shutdown` or an error.
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.accept.is_finished()
+    }
+
+    /// Waits until the accept thread has stopped and every accepted connection was handled.
+    /// This blocks forever unless `shutdown` is called or the accept thread fails.
+    ///
+    /// # Errors
+    /// The error that stopped the accept thread.
+    /// `Other` if the accept thread panicked.
+    ///
+    pub fn join(self) -> io::Result<()> {
+        let result = join_accept(self.accept);
+        join_workers(self.workers);
+        result
+    }
+
+    /// Stops accepting connections and waits until every accepted connection was handled, but not past the timeout.
+    ///
+    /// The listeners are closed, with `AcceptLoop::drain_backlog` the connections in their backlog are accepted
+    /// and handled first. If the timeout expires, connections that still wait for a worker are closed without
+    /// being handled and the worker threads of handlers that are still running are detached.
+    /// The returned report tells how many handlers were still running and how many accepted connections were abandoned,
+    /// see `ShutdownReport::abandoned`.
+    ///
+    /// # Errors
+    /// The error that stopped the accept thread if it stopped before the timeout.
+    /// `Other` if the accept thread panicked.
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn shutdown_graceful(self, timeout: Duration) -> io::Result<ShutdownReport> {
+        self.shutdown()?;
+        let deadline = Instant::now().checked_add(timeout);
+
+        let mut state = self.shared.lock();
+        while state.accepting || state.workers != 0 {
+            let wait = match deadline.map(|deadline| deadline.saturating_duration_since(Instant::now())) {
+                Some(wait) if wait.is_zero() => break,
+                Some(wait) => wait,
+                None => Duration::from_secs(u64::from(u32::MAX)),
+            };
+
+            state = self.shared.changed.wait_timeout(state, wait).unwrap_or_else(PoisonError::into_inner).0;
+        }
+
+        if state.accepting || state.workers != 0 {
+            state.abandoned = true;
+            let report = ShutdownReport {
+                running: state.running,
+                abandoned: state.queued,
+            };
+            drop(state);
+
+            //The threads are detached, they close the queued connections and end once the running handlers return.
+            return Ok(report);
+        }
+
+        drop(state);
+        let result = join_accept(self.accept);
+        join_workers(self.workers);
+        result.map(|()| ShutdownReport {
+            running: 0,
+            abandoned: 0,
+        })
+    }
+}
+
+/// Waits for the accept thread and returns the error that stopped it.
+fn join_accept(accept: JoinHandle<io::Result<()>>) -> io::Result<()> {
+    accept
+        .join()
+        .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::Other, "accept thread panicked")))
+}
+
+/// Waits for the workers, they end once the sender was dropped and the queue is empty.
+fn join_workers(workers: Vec<JoinHandle<()>>) {
+    for worker in workers {
+        //Panics of the handler are caught, the worker itself cannot panic.
+        _ = worker.join();
+    }
+}
+
+/// The loop of a worker thread, it ends once the accept thread has stopped and the queue is empty.
+fn work<S, A>(receiver: &Mutex<Receiver<(S, A)>>, handler: &(impl Fn(S, A) + Sync), shared: &Shared) {
+    let _guard = WorkerGuard { shared };
+    loop {
+        //The lock is only held while waiting, the other workers wait for the lock instead of the queue.
+        let connection = receiver.lock().unwrap_or_else(PoisonError::into_inner).recv();
+        let Ok((stream, addr)) = connection else {
+            return;
+        };
+
+        let mut state = shared.lock();
+        state.queued -= 1;
+        if state.waiting_for_slot {
+            state.waiting_for_slot = false;
+            //If waking fails the accept thread still stops on shutdown, it just accepts no more connections.
+            _ = shared.slot_freed.interrupt();
+        }
+
+        if state.abandoned {
+            continue;
+        }
+
+        state.running += 1;
+        drop(state);
+
+        //The handler is shared by reference only, the panic hook has already reported the panic.
+        _ = catch_unwind(AssertUnwindSafe(|| handler(stream, addr)));
+
+        shared.lock().running -= 1;
+        shared.changed.notify_all();
+    }
+}
+
+/// The loop of the accept thread, it returns once the interrupt is triggered or polling fails.
+/// If drain is true the backlog of every listener is accepted before returning due to the interrupt.
+fn accept_loop<L: PollAccept>(
+    listeners: &[L],
+    interrupt: &PollInterrupt,
+    sender: &SyncSender<(L::Stream, L::Addr)>,
+    shared: &Shared,
+    drain: bool,
+) -> io::Result<()> {
+    let mut set = ListenerSet::new();
+    for listener in listeners {
+        set.add(listener)?;
+    }
+
+    let wakeup = Wakeup(&shared.slot_freed);
+    let mut slot_set = ListenerSet::new();
+    slot_set.add(&wakeup)?;
+
+    //A connection that was accepted while the queue was full.
+    let mut pending = None;
+    let result = loop {
+        if let Some(connection) = pending.take() {
+            match wait_for_slot(connection, &mut slot_set, interrupt, sender, shared) {
+                Ok(None) => {}
+                Ok(Some(connection)) => {
+                    pending = Some(connection);
+                    break Ok(());
+                }
+                Err(err) => break Err(err),
+            }
+        }
+
+        let ready = match set.poll_interruptible(None, interrupt) {
+            Ok(Some(ready)) => ready,
+            Ok(None) => break Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => break Err(err),
+        };
+
+        for token in ready {
+            match accept_ready(&listeners[token], sender, shared, false) {
+                Ok(None) => {}
+                //The other listeners are accepted from once the queue has room again.
+                Ok(Some(connection)) => {
+                    pending = Some(connection);
+                    break;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    };
+
+    if let Some(connection) = pending {
+        if drain && result.is_ok() {
+            send(connection, sender, shared)?;
+        } else {
+            shared.lock().queued -= 1;
+        }
+    }
+
+    result?;
+    if drain {
+        for listener in listeners {
+            accept_ready(listener, sender, shared, true)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Polls the handle of a `PollInterrupt` like a listener.
+struct Wakeup<'a>(&'a PollInterrupt);
+
+impl PollEx for Wakeup<'_> {
+    fn raw_handle(&self) -> RawHandle {
+        self.0.raw_handle()
+    }
+}
+
+/// Queues a connection once a worker took another one out of the full queue.
+/// Returns the connection if the interrupt was triggered first.
+fn wait_for_slot<S, A>(
+    mut connection: (S, A),
+    slot_set: &mut ListenerSet,
+    interrupt: &PollInterrupt,
+    sender: &SyncSender<(S, A)>,
+    shared: &Shared,
+) -> io::Result<Option<(S, A)>> {
+    loop {
+        //Set before trying, a worker that frees a slot after the try then wakes this thread.
+        shared.lock().waiting_for_slot = true;
+        match sender.try_send(connection) {
+            Ok(()) => {
+                shared.lock().waiting_for_slot = false;
+                return Ok(None);
+            }
+            Err(TrySendError::Full(returned)) => connection = returned,
+            Err(TrySendError::Disconnected(_)) => return Err(workers_stopped(shared)),
+        }
+
+        let result = match slot_set.poll_interruptible(None, interrupt) {
+            Ok(Some(_)) => shared.slot_freed.reset(),
+            Ok(None) => return Ok(Some(connection)),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => Ok(()),
+            Err(err) => Err(err),
+        };
+
+        if let Err(err) = result {
+            shared.lock().queued -= 1;
+            return Err(err);
+        }
+    }
+}
+
+/// Accepts every connection that is pending on a listener and queues it for the workers.
+/// If the queue is full the accepted connection is returned, unless block is true, then this waits for a worker.
+fn accept_ready<L: PollAccept>(
+    listener: &L,
+    sender: &SyncSender<(L::Stream, L::Addr)>,
+    shared: &Shared,
+    block: bool,
+) -> io::Result<Option<(L::Stream, L::Addr)>> {
+    loop {
+        let (stream, addr) = match listener.accept() {
+            Ok(connection) => connection,
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+            Err(err) if matches!(err.kind(), io::ErrorKind::Interrupted | io::ErrorKind::ConnectionAborted) => continue,
+            Err(err) if is_out_of_resources(&err) => {
+                //The connection stays in the backlog, accepting again immediately would spin until a handle is closed.
+                thread::sleep(Duration::from_millis(10));
+                return Ok(None);
+            }
+            Err(err) => return Err(err),
+        };
+
+        //Some operating systems let the stream inherit the non-blocking state of the listener.
+        L::set_stream_nonblocking(&stream, false)?;
+
+        //Counted before sending, otherwise a worker could take the connection before it is counted.
+        shared.lock().queued += 1;
+        if block {
+            send((stream, addr), sender, shared)?;
+            continue;
+        }
+
+        match sender.try_send((stream, addr)) {
+            Ok(()) => {}
+            Err(TrySendError::Full(connection)) => return Ok(Some(connection)),
+            Err(TrySendError::Disconnected(_)) => return Err(workers_stopped(shared)),
+        }
+    }
+}
+
+/// Queues a connection that is already counted, waits until a worker has room for it.
+fn send<S, A>(connection: (S, A), sender: &SyncSender<(S, A)>, shared: &Shared) -> io::Result<()> {
+    sender.send(connection).map_err(|_| workers_stopped(shared))
+}
+
+/// Uncounts the connection that could not be queued and returns the error for it.
+fn workers_stopped(shared: &Shared) -> io::Error {
+    shared.lock().queued -= 1;
+    io::Error::new(io::ErrorKind::Other, "all workers of the accept loop have stopped")
+}
+
+/// Returns true if accept failed because the process or system ran out of handles or memory.
+fn is_out_of_resources(err: &io::Error) -> bool {
+    #[cfg(unix)]
+    let codes = [libc::EMFILE, libc::ENFILE, libc::ENOBUFS, libc::ENOMEM];
+    #[cfg(windows)]
+    let codes = [windows_sys::Win32::Networking::WinSock::WSAEMFILE, windows_sys::Win32::Networking::WinSock::WSAENOBUFS];
+
+    err.raw_os_error().map_or(false, |code| codes.contains(&code))
+}
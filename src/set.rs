@@ -0,0 +1,205 @@
+//! Polling of multiple listeners with a single call to the operating system.
+
+use crate::{sys, Backend, Interest, PollEx, PollInterrupt, Readiness};
+use std::io;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+/// A set of listeners that are polled together.
+///
+/// Each listener is registered with a token, polling the set blocks once on all listeners
+/// and returns the tokens of every listener that is ready.
+///
+/// ## Example
+/// ```rust
+/// use std::net::TcpListener;
+/// use std::time::Duration;
+/// use listener_poll::ListenerSet;
+///
+/// let v4 = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+/// let other = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+///
+/// let mut set = ListenerSet::new();
+/// set.add_with_token(&v4, 4).unwrap();
+/// set.add_with_token(&other, 6).unwrap();
+///
+/// let ready = set.poll(Some(Duration::from_millis(10))).unwrap();
+/// assert!(ready.is_empty());
+/// ```
+pub struct ListenerSet<'a> {
+    /// poll structures handed to the operating system, one per registered listener.
+    fds: Vec<sys::PollFd>,
+    /// the token of each registered listener, in the same order as `fds`.
+    tokens: Vec<usize>,
+    /// the operating system function that polls the set.
+    backend: Backend,
+    /// the registered listeners must outlive the set.
+    listeners: PhantomData<&'a ()>,
+}
+
+impl<'a> ListenerSet<'a> {
+    /// Creates an empty set that uses `Backend::native`.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            fds: Vec::new(),
+            tokens: Vec::new(),
+            backend: Backend::native(),
+            listeners: PhantomData,
+        }
+    }
+
+    /// Creates an empty set that is polled with the given backend.
+    ///
+    /// # Errors
+    /// `Unsupported` if the backend is not available, see `Backend::is_available`.
+    ///
+    pub fn with_backend(backend: Backend) -> io::Result<Self> {
+        if !backend.is_available() {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "poll backend is not available"));
+        }
+
+        Ok(Self {
+            backend,
+            ..Self::new()
+        })
+    }
+
+    /// Returns the backend that polls the set.
+    #[must_use]
+    pub const fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Registers a listener and returns its token, which is the index of the listener in the set.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn add<L: PollEx + ?Sized>(&mut self, listener: &'a L) -> io::Result<usize> {
+        let token = self.fds.len();
+        self.add_with_token(listener, token)?;
+        Ok(token)
+    }
+
+    /// Registers a listener with a user supplied token.
+    /// Tokens do not have to be unique.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn add_with_token<L: PollEx + ?Sized>(&mut self, listener: &'a L, token: usize) -> io::Result<()> {
+        self.fds.push(sys::new_poll_fd(listener.raw_handle(), listener.interest())?);
+        self.tokens.push(token);
+        Ok(())
+    }
+
+    /// Returns the amount of registered listeners.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.fds.len()
+    }
+
+    /// Returns true if no listener is registered.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.fds.is_empty()
+    }
+
+    /// Returns the tokens of all listeners where a later call to `accept` returns a stream or error without blocking.
+    ///
+    /// This function will return an empty Vec if the timeout elapses
+    /// or an operating system dependent spurious wakeup occurs.
+    /// This function does not guarantee that the full timeout has elapsed when it returns an empty Vec.
+    ///
+    /// Note: The same race as described in `PollEx::poll` applies to every returned listener.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn poll(&mut self, timeout: Option<Duration>) -> io::Result<Vec<usize>> {
+        if self.backend.poll_fds(&mut self.fds, timeout)? == 0 {
+            return Ok(Vec::new());
+        }
+
+        Ok(self.ready_tokens())
+    }
+
+    /// Returns the token and readiness flags of every listener for which the operating system reported an event.
+    ///
+    /// This allows to detect listeners that are in an error state or were closed, see `PollEx::poll_events`.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn poll_events(&mut self, timeout: Option<Duration>) -> io::Result<Vec<(usize, Readiness)>> {
+        if self.backend.poll_fds(&mut self.fds, timeout)? == 0 {
+            return Ok(Vec::new());
+        }
+
+        Ok(self
+            .fds
+            .iter()
+            .zip(self.tokens.iter())
+            .filter(|(fd, _)| sys::is_ready(**fd))
+            .map(|(fd, token)| (*token, sys::readiness(*fd)))
+            .collect())
+    }
+
+    /// Like `poll`, but returns `None` as soon as the interrupt is triggered.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn poll_interruptible(
+        &mut self,
+        timeout: Option<Duration>,
+        interrupt: &PollInterrupt,
+    ) -> io::Result<Option<Vec<usize>>> {
+        self.fds.push(sys::new_poll_fd(interrupt.raw_handle(), Interest::READ)?);
+        let result = self.backend.poll_fds(&mut self.fds, timeout);
+        let interrupted = self.fds.pop().map_or(false, sys::is_ready);
+
+        if result? == 0 {
+            return Ok(Some(Vec::new()));
+        }
+
+        if interrupted {
+            return Ok(None);
+        }
+
+        Ok(Some(self.ready_tokens()))
+    }
+
+    /// This function will block until at least one listener is ready and returns the tokens of all ready listeners.
+    ///
+    /// This function ignores any spurious wakeup.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn poll_until_ready(&mut self) -> io::Result<Vec<usize>> {
+        loop {
+            let ready = self.poll(None)?;
+            if !ready.is_empty() {
+                return Ok(ready);
+            }
+        }
+    }
+
+    /// Returns the tokens of all listeners for which the last poll reported an event.
+    fn ready_tokens(&self) -> Vec<usize> {
+        self.fds
+            .iter()
+            .zip(self.tokens.iter())
+            .filter(|(fd, _)| sys::is_ready(**fd))
+            .map(|(_, token)| *token)
+            .collect()
+    }
+}
+
+impl Default for ListenerSet<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
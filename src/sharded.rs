@@ -0,0 +1,185 @@
+//! Several listeners that share one address with `SO_REUSEPORT`.
+
+use crate::ListenerBuilder;
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+use std::thread::{self, JoinHandle};
+
+/// The backlog of every shard.
+const BACKLOG: i32 = 1024;
+
+/// A group of `TcpListener`s that are bound to the same address with `SO_REUSEPORT`.
+///
+/// On Linux the kernel distributes incoming connections over the shards, so every shard can be accepted
+/// from on its own thread without the threads contending for one accept queue.
+/// Each shard is a plain `TcpListener` that implements `PollEx`, so existing per-thread accept loops run unchanged.
+/// `attach_cpu_affinity` additionally makes Linux pick the shard by the CPU that received the connection.
+///
+/// Other unix systems accept `SO_REUSEPORT` but may deliver all connections to one shard.
+///
+/// ## Example
+/// ```rust
+/// use std::net::TcpStream;
+/// use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use listener_poll::{PollAccept, ShardedListener};
+///
+/// let sharded = ShardedListener::bind(([127, 0, 0, 1], 0).into(), 4).unwrap();
+/// let addr = sharded.local_addr().unwrap();
+/// let stop = Arc::new(AtomicBool::new(false));
+/// let accepted = Arc::new(AtomicUsize::new(0));
+///
+/// let (thread_stop, counter) = (Arc::clone(&stop), Arc::clone(&accepted));
+/// let threads = sharded.spawn(move |_index, listener| {
+///     //The per-thread accept loop, the timeout lets it notice the stop flag.
+///     while !thread_stop.load(Ordering::SeqCst) {
+///         if let Some((_stream, _addr)) = listener.accept_timeout(Some(Duration::from_millis(50)))? {
+///             counter.fetch_add(1, Ordering::SeqCst);
+///         }
+///     }
+///
+///     Ok(())
+/// }).unwrap();
+///
+/// let _streams = (0..4).map(|_| TcpStream::connect(addr).unwrap()).collect::<Vec<_>>();
+/// while accepted.load(Ordering::SeqCst) < 4 {
+///     std::thread::sleep(Duration::from_millis(10));
+/// }
+///
+/// stop.store(true, Ordering::SeqCst);
+/// for thread in threads {
+///     thread.join().unwrap().unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ShardedListener {
+    /// the listeners in the order they were bound, which is their index in the reuseport group.
+    shards: Vec<TcpListener>,
+}
+
+impl ShardedListener {
+    /// Binds the amount of shards to the address.
+    /// If the port is 0 the first shard picks a port and the others are bound to the same port.
+    ///
+    /// # Errors
+    /// `InvalidInput` if the amount of shards is 0.
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn bind(addr: SocketAddr, shards: usize) -> io::Result<Self> {
+        Self::bind_with(ListenerBuilder::new().backlog(BACKLOG), addr, shards)
+    }
+
+    /// Binds the amount of shards to the address with the options of the builder.
+    /// `SO_REUSEADDR` and `SO_REUSEPORT` are always set.
+    ///
+    /// # Errors
+    /// `InvalidInput` if the amount of shards is 0.
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn bind_with(builder: ListenerBuilder, addr: SocketAddr, shards: usize) -> io::Result<Self> {
+        if shards == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "a sharded listener needs at least one shard"));
+        }
+
+        let builder = builder.reuse_address(true).reuse_port(true);
+        let first = builder.bind_tcp(addr)?;
+        let addr = first.local_addr()?;
+        let mut listeners = Vec::with_capacity(shards);
+        listeners.push(first);
+        for _ in 1..shards {
+            listeners.push(builder.bind_tcp(addr)?);
+        }
+
+        Ok(Self { shards: listeners })
+    }
+
+    /// Returns the address that every shard is bound to.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.shards[0].local_addr()
+    }
+
+    /// Returns the shards, the index of a shard is its index in the reuseport group.
+    #[must_use]
+    pub fn shards(&self) -> &[TcpListener] {
+        &self.shards
+    }
+
+    /// Returns the shards.
+    #[must_use]
+    pub fn into_shards(self) -> Vec<TcpListener> {
+        self.shards
+    }
+
+    /// Makes Linux deliver a connection to the shard whose index is the CPU that received it modulo the amount of shards.
+    ///
+    /// This attaches a classic BPF program with `SO_ATTACH_REUSEPORT_CBPF` to the group. Together with
+    /// pinning the thread of shard i to CPU i and matching RSS queues, a connection is handled on one CPU.
+    /// Binding more shards to the address afterward leaves the program in place.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    #[cfg(target_os = "linux")]
+    pub fn attach_cpu_affinity(&self) -> io::Result<()> {
+        use crate::socket::unix::set_option;
+        use libc::{sock_filter, sock_fprog, BPF_A, BPF_ABS, BPF_ALU, BPF_K, BPF_LD, BPF_MOD, BPF_RET, BPF_W, SKF_AD_CPU, SKF_AD_OFF};
+
+        /// Creates one instruction, the casts convert the u32 constants of libc into the u16 opcode field.
+        #[allow(clippy::cast_possible_truncation)]
+        const fn instruction(code: u32, k: u32) -> sock_filter {
+            sock_filter {
+                code: code as u16,
+                jt: 0,
+                jf: 0,
+                k,
+            }
+        }
+
+        let shards = u32::try_from(self.shards.len()).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many shards"))?;
+        let mut program = [
+            //A = the CPU that received the packet.
+            instruction(BPF_LD | BPF_W | BPF_ABS, u32::from_ne_bytes((SKF_AD_OFF + SKF_AD_CPU).to_ne_bytes())),
+            //A = A % shards.
+            instruction(BPF_ALU | BPF_MOD | BPF_K, shards),
+            //The index of the socket in the group.
+            instruction(BPF_RET | BPF_A, 0),
+        ];
+
+        let filter = sock_fprog {
+            len: 3,
+            filter: program.as_mut_ptr(),
+        };
+
+        set_option(&self.shards[0], libc::SOL_SOCKET, libc::SO_ATTACH_REUSEPORT_CBPF, &filter)
+    }
+
+    /// Runs the function on one thread per shard, it receives the index and the listener of the shard.
+    /// Returns the threads, they return the result of the function.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors if a thread cannot be started,
+    /// the threads that were already started keep running.
+    ///
+    pub fn spawn<F>(self, func: F) -> io::Result<Vec<JoinHandle<io::Result<()>>>>
+    where
+        F: Fn(usize, TcpListener) -> io::Result<()> + Send + Sync + 'static,
+    {
+        let func = std::sync::Arc::new(func);
+        let mut threads = Vec::with_capacity(self.shards.len());
+        for (index, listener) in self.shards.into_iter().enumerate() {
+            let func = std::sync::Arc::clone(&func);
+            threads.push(
+                thread::Builder::new()
+                    .name(format!("listener_poll shard {index}"))
+                    .spawn(move || func(index, listener))?,
+            );
+        }
+
+        Ok(threads)
+    }
+}
@@ -0,0 +1,164 @@
+//! Signal masks for `PollEx::poll_with_sigmask`.
+
+use libc::{c_int, sigset_t};
+use std::io;
+use std::mem::MaybeUninit;
+use std::ptr::null;
+
+/// A set of signals, used as the signal mask of a thread.
+///
+/// ## Example
+/// ```rust
+/// use std::net::TcpListener;
+/// use std::time::Duration;
+/// use listener_poll::{PollEx, SigSet};
+///
+/// let mut blocked = SigSet::empty();
+/// blocked.add(libc::SIGHUP).unwrap();
+/// //SIGHUP is only delivered while polling.
+/// let during_poll = blocked.block_in_thread().unwrap();
+///
+/// let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+/// assert!(!listener.poll_with_sigmask(Some(Duration::from_millis(10)), &during_poll).unwrap());
+/// during_poll.set_thread_mask().unwrap();
+/// ```
+#[derive(Clone, Copy)]
+pub struct SigSet {
+    /// the libc signal set.
+    set: sigset_t,
+}
+
+/// Converts the return value of a sigset function into a result.
+fn check(result: c_int) -> io::Result<c_int> {
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(result)
+}
+
+/// Replaces, extends or reduces the signal mask of the thread and returns the previous mask.
+fn thread_mask(how: c_int, set: *const sigset_t) -> io::Result<SigSet> {
+    let mut old = MaybeUninit::<sigset_t>::uninit();
+    //pthread_sigmask returns the error instead of setting errno.
+    let result = unsafe { libc::pthread_sigmask(how, set, old.as_mut_ptr()) };
+    if result != 0 {
+        return Err(io::Error::from_raw_os_error(result));
+    }
+
+    Ok(SigSet {
+        set: unsafe { old.assume_init() },
+    })
+}
+
+impl SigSet {
+    /// Creates a set that contains no signal.
+    #[must_use]
+    pub fn empty() -> Self {
+        let mut set = MaybeUninit::<sigset_t>::uninit();
+        //sigemptyset cannot fail for a valid pointer.
+        unsafe { libc::sigemptyset(set.as_mut_ptr()) };
+        Self {
+            set: unsafe { set.assume_init() },
+        }
+    }
+
+    /// Creates a set that contains every signal.
+    #[must_use]
+    pub fn full() -> Self {
+        let mut set = MaybeUninit::<sigset_t>::uninit();
+        //sigfillset cannot fail for a valid pointer.
+        unsafe { libc::sigfillset(set.as_mut_ptr()) };
+        Self {
+            set: unsafe { set.assume_init() },
+        }
+    }
+
+    /// Returns the signal mask of the calling thread.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn current() -> io::Result<Self> {
+        thread_mask(libc::SIG_BLOCK, null())
+    }
+
+    /// Adds a signal to the set.
+    ///
+    /// # Errors
+    /// `InvalidInput` if the signal number is not valid.
+    ///
+    pub fn add(&mut self, signal: c_int) -> io::Result<()> {
+        check(unsafe { libc::sigaddset(&mut self.set, signal) }).map(drop)
+    }
+
+    /// Removes a signal from the set.
+    ///
+    /// # Errors
+    /// `InvalidInput` if the signal number is not valid.
+    ///
+    pub fn remove(&mut self, signal: c_int) -> io::Result<()> {
+        check(unsafe { libc::sigdelset(&mut self.set, signal) }).map(drop)
+    }
+
+    /// Returns true if the signal is in the set.
+    ///
+    /// # Errors
+    /// `InvalidInput` if the signal number is not valid.
+    ///
+    pub fn contains(&self, signal: c_int) -> io::Result<bool> {
+        check(unsafe { libc::sigismember(&self.set, signal) }).map(|result| result != 0)
+    }
+
+    /// Blocks all signals of this set in the calling thread in addition to the already blocked signals.
+    /// Returns the previous signal mask of the thread.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn block_in_thread(&self) -> io::Result<Self> {
+        thread_mask(libc::SIG_BLOCK, &self.set)
+    }
+
+    /// Replaces the signal mask of the calling thread with this set.
+    /// Returns the previous signal mask of the thread.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn set_thread_mask(&self) -> io::Result<Self> {
+        thread_mask(libc::SIG_SETMASK, &self.set)
+    }
+
+    /// Returns a pointer to the libc signal set.
+    #[must_use]
+    pub const fn as_ptr(&self) -> *const sigset_t {
+        &self.set
+    }
+}
+
+impl std::fmt::Debug for SigSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        //Signal numbers are not contiguous on every target, only the commonly known ones are listed.
+        let known = [
+            ("SIGHUP", libc::SIGHUP),
+            ("SIGINT", libc::SIGINT),
+            ("SIGQUIT", libc::SIGQUIT),
+            ("SIGPIPE", libc::SIGPIPE),
+            ("SIGALRM", libc::SIGALRM),
+            ("SIGTERM", libc::SIGTERM),
+            ("SIGCHLD", libc::SIGCHLD),
+            ("SIGUSR1", libc::SIGUSR1),
+            ("SIGUSR2", libc::SIGUSR2),
+        ];
+
+        f.debug_set()
+            .entries(
+                known
+                    .iter()
+                    .filter(|(_, signal)| self.contains(*signal).unwrap_or(false))
+                    .map(|(name, _)| name),
+            )
+            .finish()
+    }
+}
@@ -0,0 +1,301 @@
+//! Raw socket functionality that the stdlib does not expose.
+
+use std::io;
+use std::net::{SocketAddr, TcpStream};
+
+/// Creates an unconnected tcp socket for the address family of the address.
+/// The socket is wrapped into a `TcpStream` so it is closed on drop.
+#[cfg(unix)]
+pub fn new_tcp_socket(addr: &SocketAddr) -> io::Result<TcpStream> {
+    Ok(TcpStream::from(unix::new_socket(unix::domain(addr), libc::SOCK_STREAM, true)?))
+}
+
+/// Creates an unconnected tcp socket for the address family of the address.
+/// The socket is wrapped into a `TcpStream` so it is closed on drop.
+#[cfg(windows)]
+pub fn new_tcp_socket(addr: &SocketAddr) -> io::Result<TcpStream> {
+    use std::os::windows::io::FromRawSocket;
+    let socket = windows::new_socket(addr)?;
+    Ok(unsafe { TcpStream::from_raw_socket(socket) })
+}
+
+/// Starts to connect a socket.
+/// Returns true if the connection was established immediately and false if it is still in progress.
+/// The socket should be non-blocking, otherwise this blocks until the connection is established.
+#[cfg(unix)]
+pub fn start_connect(socket: &TcpStream, addr: &SocketAddr) -> io::Result<bool> {
+    use std::os::fd::AsRawFd;
+    let (storage, len) = unix::raw_addr(addr);
+    let result = unsafe { libc::connect(socket.as_raw_fd(), std::ptr::addr_of!(storage).cast(), len) };
+    if result == 0 {
+        return Ok(true);
+    }
+
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        //EINTR means that the connection continues asynchronously.
+        Some(libc::EINPROGRESS | libc::EINTR) => Ok(false),
+        _ => Err(err),
+    }
+}
+
+/// Starts to connect a socket.
+/// Returns true if the connection was established immediately and false if it is still in progress.
+/// The socket should be non-blocking, otherwise this blocks until the connection is established.
+#[cfg(windows)]
+pub fn start_connect(socket: &TcpStream, addr: &SocketAddr) -> io::Result<bool> {
+    use std::os::windows::io::AsRawSocket;
+    use windows_sys::Win32::Networking::WinSock::{connect, WSAGetLastError, SOCKET_ERROR, WSAEWOULDBLOCK};
+
+    let handle = windows::socket_handle(socket.as_raw_socket())?;
+    let (storage, len) = windows::raw_addr(addr);
+    let result = unsafe { connect(handle, std::ptr::addr_of!(storage).cast(), len) };
+    if result != SOCKET_ERROR {
+        return Ok(true);
+    }
+
+    let code = unsafe { WSAGetLastError() };
+    if code == WSAEWOULDBLOCK {
+        return Ok(false);
+    }
+
+    Err(io::Error::from_raw_os_error(code))
+}
+
+/// Unix specific helpers.
+#[cfg(unix)]
+pub mod unix {
+    use libc::{c_int, c_void, sa_family_t, sockaddr_in, sockaddr_in6, sockaddr_storage, sockaddr_un, socklen_t};
+    use std::io;
+    use std::mem::size_of;
+    use std::net::SocketAddr;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+    /// `AF_INET` as stored in a socket address.
+    //The address family constants are tiny.
+    #[allow(clippy::cast_possible_truncation)]
+    const AF_INET: sa_family_t = libc::AF_INET as sa_family_t;
+
+    /// `AF_INET6` as stored in a socket address.
+    //The address family constants are tiny.
+    #[allow(clippy::cast_possible_truncation)]
+    const AF_INET6: sa_family_t = libc::AF_INET6 as sa_family_t;
+
+    /// `AF_UNIX` as stored in a socket address.
+    //The address family constants are tiny.
+    #[allow(clippy::cast_possible_truncation)]
+    const AF_UNIX: sa_family_t = libc::AF_UNIX as sa_family_t;
+
+    /// Returns the socket domain for the address.
+    pub const fn domain(addr: &SocketAddr) -> c_int {
+        match addr {
+            SocketAddr::V4(_) => libc::AF_INET,
+            SocketAddr::V6(_) => libc::AF_INET6,
+        }
+    }
+
+    /// Converts the size of an address structure into the length field of the targets that have one.
+    #[cfg(any(target_vendor = "apple", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd", target_os = "dragonfly"))]
+    fn addr_len(size: usize) -> u8 {
+        u8::try_from(size).expect("Unreachable: size of a sockaddr does not fit into u8")
+    }
+
+    /// Converts an address into the libc representation.
+    //Some targets have additional fields that must be zero.
+    #[allow(clippy::needless_update)]
+    pub fn raw_addr(addr: &SocketAddr) -> (sockaddr_storage, socklen_t) {
+        let mut storage: sockaddr_storage = unsafe { std::mem::zeroed() };
+        let len = match addr {
+            SocketAddr::V4(addr) => {
+                let raw = sockaddr_in {
+                    #[cfg(any(target_vendor = "apple", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd", target_os = "dragonfly"))]
+                    sin_len: addr_len(size_of::<sockaddr_in>()),
+                    sin_family: AF_INET,
+                    sin_port: addr.port().to_be(),
+                    sin_addr: libc::in_addr {
+                        s_addr: u32::from_ne_bytes(addr.ip().octets()),
+                    },
+                    ..unsafe { std::mem::zeroed() }
+                };
+                unsafe { std::ptr::addr_of_mut!(storage).cast::<sockaddr_in>().write(raw) };
+                size_of::<sockaddr_in>()
+            }
+            SocketAddr::V6(addr) => {
+                let raw = sockaddr_in6 {
+                    #[cfg(any(target_vendor = "apple", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd", target_os = "dragonfly"))]
+                    sin6_len: addr_len(size_of::<sockaddr_in6>()),
+                    sin6_family: AF_INET6,
+                    sin6_port: addr.port().to_be(),
+                    sin6_flowinfo: addr.flowinfo(),
+                    sin6_addr: libc::in6_addr {
+                        s6_addr: addr.ip().octets(),
+                    },
+                    sin6_scope_id: addr.scope_id(),
+                    ..unsafe { std::mem::zeroed() }
+                };
+                unsafe { std::ptr::addr_of_mut!(storage).cast::<sockaddr_in6>().write(raw) };
+                size_of::<sockaddr_in6>()
+            }
+        };
+
+        (storage, socklen_t::try_from(len).expect("Unreachable: size of a sockaddr does not fit into socklen_t"))
+    }
+
+    /// Converts the bytes of a unix socket path into the libc representation.
+    /// A path that starts with a zero byte is an address in the abstract namespace of Linux.
+    pub fn unix_addr(path: &[u8]) -> io::Result<(sockaddr_un, socklen_t)> {
+        let mut raw: sockaddr_un = unsafe { std::mem::zeroed() };
+        raw.sun_family = AF_UNIX;
+
+        //A path must leave room for the terminating zero byte, an abstract address does not have one.
+        let abstract_address = path.first() == Some(&0);
+        if path.len() + usize::from(!abstract_address) > raw.sun_path.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "path is too long for a unix socket address"));
+        }
+
+        for (target, byte) in raw.sun_path.iter_mut().zip(path) {
+            *target = libc::c_char::from_ne_bytes([*byte]);
+        }
+
+        let len = size_of::<sockaddr_un>() - raw.sun_path.len() + path.len() + usize::from(!abstract_address);
+        #[cfg(any(target_vendor = "apple", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd", target_os = "dragonfly"))]
+        {
+            raw.sun_len = addr_len(len);
+        }
+
+        Ok((raw, socklen_t::try_from(len).expect("Unreachable: size of a sockaddr does not fit into socklen_t")))
+    }
+
+    /// Sets a socket option.
+    pub fn set_option<T>(fd: &impl AsRawFd, level: c_int, option: c_int, value: &T) -> io::Result<()> {
+        let len = socklen_t::try_from(size_of::<T>()).expect("Unreachable: size of a socket option does not fit into socklen_t");
+        if unsafe { libc::setsockopt(fd.as_raw_fd(), level, option, (value as *const T).cast::<c_void>(), len) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    /// Creates a socket that is close-on-exec if cloexec is true.
+    #[cfg(not(target_vendor = "apple"))]
+    pub fn new_socket(domain: c_int, ty: c_int, cloexec: bool) -> io::Result<OwnedFd> {
+        let ty = if cloexec { ty | libc::SOCK_CLOEXEC } else { ty };
+        let fd = unsafe { libc::socket(domain, ty, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
+    /// Creates a socket that is close-on-exec if cloexec is true.
+    /// Apple has no `SOCK_CLOEXEC`, the stdlib also sets `SO_NOSIGPIPE` on its sockets.
+    #[cfg(target_vendor = "apple")]
+    pub fn new_socket(domain: c_int, ty: c_int, cloexec: bool) -> io::Result<OwnedFd> {
+        let fd = unsafe { libc::socket(domain, ty, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        if cloexec && unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        set_option::<c_int>(&fd, libc::SOL_SOCKET, libc::SO_NOSIGPIPE, &1)?;
+        Ok(fd)
+    }
+}
+
+/// Windows specific helpers.
+#[cfg(windows)]
+mod windows {
+    use std::io;
+    use std::mem::size_of;
+    use std::net::SocketAddr;
+    use std::os::windows::io::RawSocket;
+    use std::sync::Once;
+    use windows_sys::Win32::Networking::WinSock::{
+        WSAGetLastError, WSASocketW, WSAStartup, AF_INET, AF_INET6, INVALID_SOCKET, IN6_ADDR, IN6_ADDR_0, IN_ADDR, IN_ADDR_0, IPPROTO_TCP, SOCKADDR_IN, SOCKADDR_IN6, SOCKADDR_IN6_0,
+        SOCKADDR_STORAGE, SOCKET, SOCK_STREAM, WSADATA, WSA_FLAG_NO_HANDLE_INHERIT, WSA_FLAG_OVERLAPPED,
+    };
+
+    /// Converts a raw socket of the stdlib into a windows-sys socket.
+    pub fn socket_handle(socket: RawSocket) -> io::Result<SOCKET> {
+        SOCKET::try_from(socket)
+            //Unreachable unless the stdlib or windows-sys or both fucked up!
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "as_raw_socket handle does not fit into windows_sys::Win32::Networking::WinSock::SOCKET"))
+    }
+
+    /// Converts an address into the winsock representation.
+    pub fn raw_addr(addr: &SocketAddr) -> (SOCKADDR_STORAGE, i32) {
+        let mut storage = SOCKADDR_STORAGE::default();
+        let len = match addr {
+            SocketAddr::V4(addr) => {
+                let raw = SOCKADDR_IN {
+                    sin_family: AF_INET,
+                    sin_port: addr.port().to_be(),
+                    sin_addr: IN_ADDR {
+                        S_un: IN_ADDR_0 {
+                            S_addr: u32::from_ne_bytes(addr.ip().octets()),
+                        },
+                    },
+                    sin_zero: [0; 8],
+                };
+                unsafe { std::ptr::addr_of_mut!(storage).cast::<SOCKADDR_IN>().write(raw) };
+                size_of::<SOCKADDR_IN>()
+            }
+            SocketAddr::V6(addr) => {
+                let raw = SOCKADDR_IN6 {
+                    sin6_family: AF_INET6,
+                    sin6_port: addr.port().to_be(),
+                    sin6_flowinfo: addr.flowinfo(),
+                    sin6_addr: IN6_ADDR {
+                        u: IN6_ADDR_0 { Byte: addr.ip().octets() },
+                    },
+                    Anonymous: SOCKADDR_IN6_0 {
+                        sin6_scope_id: addr.scope_id(),
+                    },
+                };
+                unsafe { std::ptr::addr_of_mut!(storage).cast::<SOCKADDR_IN6>().write(raw) };
+                size_of::<SOCKADDR_IN6>()
+            }
+        };
+
+        (storage, i32::try_from(len).expect("Unreachable: size of a sockaddr does not fit into i32"))
+    }
+
+    /// Creates a non-inheritable tcp socket for the address family of the address.
+    pub fn new_socket(addr: &SocketAddr) -> io::Result<RawSocket> {
+        /// Winsock must be initialized before the first socket is created, the stdlib does this lazily as well.
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            let mut data = WSADATA::default();
+            //Failure surfaces as an error of WSASocketW below.
+            unsafe { WSAStartup(0x202, &mut data) };
+        });
+
+        let family = match addr {
+            SocketAddr::V4(_) => AF_INET,
+            SocketAddr::V6(_) => AF_INET6,
+        };
+
+        let socket = unsafe {
+            WSASocketW(
+                i32::from(family),
+                SOCK_STREAM,
+                IPPROTO_TCP,
+                std::ptr::null(),
+                0,
+                WSA_FLAG_OVERLAPPED | WSA_FLAG_NO_HANDLE_INHERIT,
+            )
+        };
+        if socket == INVALID_SOCKET {
+            return Err(io::Error::from_raw_os_error(unsafe { WSAGetLastError() }));
+        }
+
+        RawSocket::try_from(socket)
+            //Unreachable unless the stdlib or windows-sys or both fucked up!
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "windows_sys::Win32::Networking::WinSock::SOCKET does not fit into RawSocket"))
+    }
+}
@@ -0,0 +1,77 @@
+//! Conversions between the listeners of std and tokio.
+
+use std::io;
+
+/// Conversion of a std listener that is used with `PollEx` into its tokio counterpart and back.
+///
+/// This allows moving parts of a server to tokio one listener at a time. `into_tokio` switches the
+/// listener to non-blocking and registers it with the reactor of the current tokio runtime,
+/// `from_tokio` deregisters it and switches it back to blocking.
+/// The handle is the same, so the socket keeps its address and its queue of pending connections.
+///
+/// ## Example
+/// ```rust
+/// use std::net::TcpListener;
+/// use std::time::Duration;
+/// use listener_poll::{IntoTokio, PollEx};
+///
+/// let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+/// assert!(!listener.poll(Some(Duration::from_millis(10))).unwrap());
+///
+/// let runtime = tokio::runtime::Builder::new_current_thread().enable_io().build().unwrap();
+/// let listener = runtime.block_on(async { listener.into_tokio() }).unwrap();
+/// let listener = TcpListener::from_tokio(listener).unwrap();
+/// assert!(!listener.poll(Some(Duration::from_millis(10))).unwrap());
+/// ```
+pub trait IntoTokio: Sized {
+    /// The tokio listener.
+    type Tokio;
+
+    /// Switches the listener to non-blocking and registers it with the tokio runtime.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    /// # Panics
+    /// If called outside a tokio runtime or the runtime has IO disabled.
+    ///
+    fn into_tokio(self) -> io::Result<Self::Tokio>;
+
+    /// Deregisters the listener from the tokio runtime and switches it back to blocking.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    fn from_tokio(listener: Self::Tokio) -> io::Result<Self>;
+}
+
+impl IntoTokio for std::net::TcpListener {
+    type Tokio = tokio::net::TcpListener;
+
+    fn into_tokio(self) -> io::Result<Self::Tokio> {
+        self.set_nonblocking(true)?;
+        tokio::net::TcpListener::from_std(self)
+    }
+
+    fn from_tokio(listener: Self::Tokio) -> io::Result<Self> {
+        let listener = listener.into_std()?;
+        listener.set_nonblocking(false)?;
+        Ok(listener)
+    }
+}
+
+#[cfg(unix)]
+impl IntoTokio for std::os::unix::net::UnixListener {
+    type Tokio = tokio::net::UnixListener;
+
+    fn into_tokio(self) -> io::Result<Self::Tokio> {
+        self.set_nonblocking(true)?;
+        tokio::net::UnixListener::from_std(self)
+    }
+
+    fn from_tokio(listener: Self::Tokio) -> io::Result<Self> {
+        let listener = listener.into_std()?;
+        listener.set_nonblocking(false)?;
+        Ok(listener)
+    }
+}
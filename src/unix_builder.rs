@@ -0,0 +1,407 @@
+//! Creation of unix listeners that manage their socket file.
+
+use crate::socket::unix::{new_socket, unix_addr};
+use crate::{ListenerBuilder, PollAccept, PollEx, RawHandle};
+use libc::{sockaddr_un, socklen_t, AF_UNIX, SOCK_STREAM};
+use std::ffi::CString;
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+use std::os::unix::net::{SocketAddr, UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How long a socket must keep refusing connections before it is considered stale.
+const STALE_RECHECK: Duration = Duration::from_millis(100);
+
+/// Builder for `UnixListener`s that takes care of the socket file.
+///
+/// Binding to a path fails with `AddrInUse` if the file exists. With `remove_stale` a socket file that
+/// nothing listens on anymore, because its process died, is removed and the bind is retried once.
+/// Whether something listens is checked by connecting without blocking, other files and sockets that accept are never removed.
+/// A listener that was just bound refuses connections until it listens, so a socket is only stale
+/// if it still refuses connections after a short wait.
+///
+/// The mode and owner are set between bind and listen, so no connection is accepted with the permissions of the umask.
+///
+/// ## Example
+/// ```rust
+/// use std::time::Duration;
+/// use listener_poll::{PollEx, UnixListenerBuilder};
+///
+/// let path = std::env::temp_dir().join(format!("listener_poll_doc_{}.sock", std::process::id()));
+/// let listener = UnixListenerBuilder::new()
+///     .remove_stale(true)
+///     .mode(0o660)
+///     .bind(&path)
+///     .unwrap();
+///
+/// assert!(!listener.poll(Some(Duration::from_millis(10))).unwrap());
+/// drop(listener);
+/// assert!(!path.exists());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[must_use]
+pub struct UnixListenerBuilder {
+    /// backlog and close-on-exec.
+    builder: ListenerBuilder,
+    /// the permissions of the socket file.
+    mode: Option<u32>,
+    /// the user of the socket file.
+    uid: Option<u32>,
+    /// the group of the socket file.
+    gid: Option<u32>,
+    /// remove a socket file that nothing listens on.
+    remove_stale: bool,
+    /// remove the socket file when the listener is dropped.
+    remove_on_drop: bool,
+}
+
+impl UnixListenerBuilder {
+    /// Creates a builder that removes the socket file on drop and does not remove stale files.
+    pub const fn new() -> Self {
+        Self {
+            builder: ListenerBuilder::new(),
+            mode: None,
+            uid: None,
+            gid: None,
+            remove_stale: false,
+            remove_on_drop: true,
+        }
+    }
+
+    /// Sets the length of the queue of connections that were not accepted yet.
+    pub const fn backlog(mut self, backlog: i32) -> Self {
+        self.builder = self.builder.backlog(backlog);
+        self
+    }
+
+    /// Sets if the listener is closed when the process executes another program, on by default.
+    pub const fn cloexec(mut self, cloexec: bool) -> Self {
+        self.builder = self.builder.cloexec(cloexec);
+        self
+    }
+
+    /// Sets the permissions of the socket file, connecting needs write permission.
+    pub const fn mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Sets the user and group of the socket file, None keeps the user or group of the process.
+    /// Changing the user usually needs privileges.
+    pub const fn owner(mut self, uid: Option<u32>, gid: Option<u32>) -> Self {
+        self.uid = uid;
+        self.gid = gid;
+        self
+    }
+
+    /// Sets if a socket file that nothing listens on is removed before binding.
+    pub const fn remove_stale(mut self, remove: bool) -> Self {
+        self.remove_stale = remove;
+        self
+    }
+
+    /// Sets if the socket file is removed when the listener is dropped, on by default.
+    /// The file is only removed if it is still the socket that the listener created.
+    pub const fn remove_on_drop(mut self, remove: bool) -> Self {
+        self.remove_on_drop = remove;
+        self
+    }
+
+    /// Returns the length of the queue of connections that were not accepted yet.
+    #[must_use]
+    pub const fn get_backlog(&self) -> i32 {
+        self.builder.get_backlog()
+    }
+
+    /// Returns if the listener is closed when the process executes another program.
+    #[must_use]
+    pub const fn get_cloexec(&self) -> bool {
+        self.builder.get_cloexec()
+    }
+
+    /// Returns the permissions of the socket file.
+    #[must_use]
+    pub const fn get_mode(&self) -> Option<u32> {
+        self.mode
+    }
+
+    /// Returns the user and group of the socket file.
+    #[must_use]
+    pub const fn get_owner(&self) -> (Option<u32>, Option<u32>) {
+        (self.uid, self.gid)
+    }
+
+    /// Returns if a socket file that nothing listens on is removed before binding.
+    #[must_use]
+    pub const fn get_remove_stale(&self) -> bool {
+        self.remove_stale
+    }
+
+    /// Returns if the socket file is removed when the listener is dropped.
+    #[must_use]
+    pub const fn get_remove_on_drop(&self) -> bool {
+        self.remove_on_drop
+    }
+
+    /// Creates a listener that is bound to the path.
+    ///
+    /// # Errors
+    /// `AddrInUse` if the file exists and is not a stale socket that may be removed.
+    /// `InvalidInput` if the path is too long for a unix socket address.
+    /// Operating system and implementation-specific errors, the socket file is removed in that case.
+    ///
+    pub fn bind<P: AsRef<Path>>(&self, path: P) -> io::Result<BoundUnixListener> {
+        let path = path.as_ref();
+        let (address, length) = unix_addr(path.as_os_str().as_bytes())?;
+        if path.as_os_str().as_bytes().first().map_or(true, |byte| *byte == 0) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the path of a unix socket file must not be empty or start with a zero byte"));
+        }
+
+        let (listener, file) = match self.bind_file(&address, length, path) {
+            Err(err) if err.kind() == io::ErrorKind::AddrInUse && self.remove_stale && remove_if_stale(path, &address, length)? => self.bind_file(&address, length, path),
+            result => result,
+        }?;
+
+        Ok(BoundUnixListener {
+            listener: Some(listener),
+            file: self.remove_on_drop.then_some(file),
+        })
+    }
+
+    /// Creates a listener that is bound to a name in the abstract namespace of Linux.
+    /// Abstract addresses have no file, so the mode, owner and removal do not apply,
+    /// the address is released when the listener is closed.
+    ///
+    /// # Errors
+    /// `InvalidInput` if the name is too long or a mode or owner is set, abstract addresses have no permissions.
+    /// Operating system and implementation-specific errors.
+    ///
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn bind_abstract(&self, name: &[u8]) -> io::Result<BoundUnixListener> {
+        if self.mode.is_some() || self.uid.is_some() || self.gid.is_some() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "abstract unix socket addresses have no mode or owner"));
+        }
+
+        let mut path = Vec::with_capacity(name.len() + 1);
+        path.push(0);
+        path.extend_from_slice(name);
+        let (address, length) = unix_addr(&path)?;
+        Ok(BoundUnixListener {
+            listener: Some(self.builder.bind_unix_raw(&address, length, || Ok(()))?),
+            file: None,
+        })
+    }
+
+    /// Binds to the path and sets the mode and owner of the socket file before listening.
+    /// The file is removed if anything fails after it was created.
+    fn bind_file(&self, address: &sockaddr_un, length: socklen_t, path: &Path) -> io::Result<(UnixListener, SocketFile)> {
+        let mut file = None;
+        let result = self.builder.bind_unix_raw(address, length, || {
+            let metadata = std::fs::symlink_metadata(path)?;
+            file = Some(SocketFile {
+                path: path.to_path_buf(),
+                dev: metadata.dev(),
+                ino: metadata.ino(),
+            });
+
+            self.set_owner(path)
+        });
+
+        match result {
+            Ok(listener) => Ok((listener, file.expect("Unreachable: the socket file is recorded before listening"))),
+            Err(err) => {
+                if let Some(file) = file {
+                    file.remove();
+                }
+
+                Err(err)
+            }
+        }
+    }
+
+    /// Sets the mode and owner of the socket file.
+    fn set_owner(&self, path: &Path) -> io::Result<()> {
+        if self.uid.is_some() || self.gid.is_some() {
+            let path = CString::new(path.as_os_str().as_bytes()).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+            //-1 keeps the user or group.
+            let uid = self.uid.unwrap_or(libc::uid_t::MAX);
+            let gid = self.gid.unwrap_or(libc::gid_t::MAX);
+            if unsafe { libc::lchown(path.as_ptr(), uid, gid) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        if let Some(mode) = self.mode {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for UnixListenerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Removes the file if it is a socket that refuses connections twice, returns if it was removed.
+fn remove_if_stale(path: &Path, address: &sockaddr_un, length: socklen_t) -> io::Result<bool> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        //Removed by someone else in the meantime, binding again may succeed.
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(true),
+        Err(err) => return Err(err),
+    };
+
+    if !metadata.file_type().is_socket() || !refuses(address, length)? {
+        return Ok(false);
+    }
+
+    //Another process may be between bind and listen, it listens by the time of the second check.
+    std::thread::sleep(STALE_RECHECK);
+    if !refuses(address, length)? {
+        return Ok(false);
+    }
+
+    match std::fs::symlink_metadata(path) {
+        Ok(current) if current.dev() == metadata.dev() && current.ino() == metadata.ino() => {}
+        //Another socket was bound to the path in the meantime.
+        Ok(_) => return Ok(false),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(true),
+        Err(err) => return Err(err),
+    }
+
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(true),
+    }
+}
+
+/// Connects to the address without blocking, returns true if the connection is refused or the file is gone.
+fn refuses(address: &sockaddr_un, length: socklen_t) -> io::Result<bool> {
+    let fd = new_socket(AF_UNIX, SOCK_STREAM, true)?;
+    if unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFL, libc::O_NONBLOCK) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    if unsafe { libc::connect(fd.as_raw_fd(), (address as *const sockaddr_un).cast(), length) } == 0 {
+        return Ok(false);
+    }
+
+    //A full backlog fails with EAGAIN or completes later, both mean that something listens.
+    Ok(matches!(io::Error::last_os_error().raw_os_error(), Some(libc::ECONNREFUSED | libc::ENOENT)))
+}
+
+/// The socket file of a listener and its identity when it was bound.
+#[derive(Debug)]
+struct SocketFile {
+    /// where the file was created.
+    path: PathBuf,
+    /// the device of the file.
+    dev: u64,
+    /// the inode of the file.
+    ino: u64,
+}
+
+impl SocketFile {
+    /// Removes the file if it is still the socket that was bound, another process may have bound the path since.
+    fn remove(&self) {
+        if let Ok(metadata) = std::fs::symlink_metadata(&self.path) {
+            if metadata.dev() == self.dev && metadata.ino() == self.ino {
+                _ = std::fs::remove_file(&self.path);
+            }
+        }
+    }
+}
+
+/// A `UnixListener` that was created by `UnixListenerBuilder`.
+///
+/// It implements `PollEx` and `PollAccept` and dereferences to the `UnixListener`.
+/// On drop the socket file is removed, unless another socket was bound to the path since.
+#[derive(Debug)]
+pub struct BoundUnixListener {
+    /// the listener, only None after `into_inner` took it.
+    listener: Option<UnixListener>,
+    /// the file to remove on drop.
+    file: Option<SocketFile>,
+}
+
+impl BoundUnixListener {
+    /// Returns the listener.
+    #[must_use]
+    pub fn get_ref(&self) -> &UnixListener {
+        self.listener()
+    }
+
+    /// Returns the path of the socket file that is removed on drop.
+    #[must_use]
+    pub fn path(&self) -> Option<&Path> {
+        self.file.as_ref().map(|file| file.path.as_path())
+    }
+
+    /// Returns the listener, the socket file is then no longer removed.
+    #[must_use]
+    pub fn into_inner(mut self) -> UnixListener {
+        self.file = None;
+        self.take_listener()
+    }
+
+    /// Returns the listener, it is always present before `into_inner`.
+    fn listener(&self) -> &UnixListener {
+        self.listener.as_ref().expect("Unreachable: the listener is only taken by into_inner")
+    }
+
+    /// Moves the listener out, used by `into_inner`.
+    fn take_listener(&mut self) -> UnixListener {
+        self.listener.take().expect("Unreachable: the listener is only taken once")
+    }
+}
+
+impl std::ops::Deref for BoundUnixListener {
+    type Target = UnixListener;
+
+    fn deref(&self) -> &UnixListener {
+        self.get_ref()
+    }
+}
+
+impl AsFd for BoundUnixListener {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.get_ref().as_fd()
+    }
+}
+
+impl PollEx for BoundUnixListener {
+    fn raw_handle(&self) -> RawHandle {
+        self.get_ref().as_raw_fd()
+    }
+}
+
+impl PollAccept for BoundUnixListener {
+    type Stream = UnixStream;
+    type Addr = SocketAddr;
+
+    fn accept(&self) -> io::Result<(Self::Stream, Self::Addr)> {
+        self.get_ref().accept()
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.get_ref().set_nonblocking(nonblocking)
+    }
+
+    fn set_stream_nonblocking(stream: &Self::Stream, nonblocking: bool) -> io::Result<()> {
+        stream.set_nonblocking(nonblocking)
+    }
+}
+
+impl Drop for BoundUnixListener {
+    fn drop(&mut self) {
+        if let Some(file) = self.file.take() {
+            file.remove();
+        }
+    }
+}
@@ -0,0 +1,473 @@
+//! `io_uring` backend with persistent registrations.
+
+use crate::epoll::{interest_events, readiness};
+use crate::{sys, Events, Interest, PollEx, RawHandle, Readiness};
+use io_uring::types::{Fd, Timespec};
+use io_uring::{cqueue, opcode, squeue, IoUring, Probe};
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+/// Size of the submission queue, larger amounts of registrations are armed in several batches.
+const ENTRIES: u32 = 256;
+
+/// `user_data` of submissions whose completion is not interesting.
+const IGNORED: u64 = 0;
+
+/// `IORING_ENTER_GETEVENTS`, waits for completions.
+const ENTER_GETEVENTS: u32 = 1;
+
+/// A registered handle.
+#[derive(Debug)]
+struct Registration {
+    /// the handle.
+    fd: RawHandle,
+    /// the token that is reported in events.
+    token: usize,
+    /// what the handle is polled for.
+    interest: Interest,
+    /// the `user_data` of the poll that is currently submitted for this handle, if any.
+    armed: Option<u64>,
+}
+
+/// State that is guarded by the mutex.
+struct Inner {
+    /// the ring, None if `io_uring` is not available and ppoll is used.
+    ring: Option<IoUring>,
+    /// the registered handles.
+    registrations: Vec<Registration>,
+    /// the `user_data` of the next poll submission.
+    next_id: u64,
+    /// an eventfd that never becomes readable, its poll carries the linked timeout of a wait.
+    timer: OwnedFd,
+    /// the `user_data` of the timer poll of the current wait, None once the timeout fired.
+    timeout: Option<u64>,
+}
+
+/// Poller that registers handles once and waits for all of them with `io_uring`.
+///
+/// This has the same API as `EpollPoller`, a poll is submitted for every registered handle and stays
+/// submitted between waits, a wait only resubmits the polls that completed in the previous wait.
+/// Waits have nanosecond timeouts.
+///
+/// The timeout of a wait is an `IORING_OP_LINK_TIMEOUT` that is linked to a poll of an eventfd which never
+/// becomes readable, so it cancels only that poll and the polls of the registrations stay submitted.
+///
+/// Requires Linux 5.6 or later. If the kernel does not support `io_uring` or it is disabled,
+/// for example by the `kernel.io_uring_disabled` sysctl or a seccomp filter,
+/// this falls back to ppoll over all registered handles.
+///
+/// Registrations can be changed while another thread waits, with `io_uring` a wait that is in progress
+/// reports handles that were added meanwhile, with ppoll only the next wait does.
+/// Only one thread waits at a time, a second wait blocks until the first one returns.
+///
+/// ## Example
+/// ```rust
+/// use std::net::{TcpListener, TcpStream};
+/// use std::time::Duration;
+/// use listener_poll::{Events, Interest, UringPoller};
+///
+/// let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+/// let poller = UringPoller::new().unwrap();
+/// poller.add(&listener, 7, Interest::READ).unwrap();
+///
+/// let _stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+/// let mut events = Events::with_capacity(16);
+/// poller.wait(&mut events, Some(Duration::from_secs(5))).unwrap();
+/// assert_eq!(7, events.iter().next().unwrap().token());
+/// ```
+pub struct UringPoller {
+    /// the ring and the registrations.
+    inner: Mutex<Inner>,
+    /// held by the thread that waits.
+    waiter: Mutex<()>,
+}
+
+impl UringPoller {
+    /// Creates a new poller without registrations.
+    /// Falls back to ppoll if `io_uring` is not available.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn new() -> io::Result<Self> {
+        let ring = match IoUring::new(ENTRIES) {
+            Ok(ring) if is_supported(&ring) => Some(ring),
+            Ok(_) => None,
+            Err(err) if matches!(err.raw_os_error(), Some(libc::ENOSYS | libc::EPERM | libc::EACCES)) => None,
+            Err(err) => return Err(err),
+        };
+
+        let timer = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if timer < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            inner: Mutex::new(Inner {
+                ring,
+                registrations: Vec::new(),
+                next_id: IGNORED + 1,
+                timer: unsafe { OwnedFd::from_raw_fd(timer) },
+                timeout: None,
+            }),
+            waiter: Mutex::new(()),
+        })
+    }
+
+    /// Returns true if `io_uring` is used and false if this poller fell back to ppoll.
+    #[must_use]
+    pub fn is_io_uring(&self) -> bool {
+        self.lock().ring.is_some()
+    }
+
+    /// Registers a handle, waits report its token once it is ready for the interest.
+    ///
+    /// # Errors
+    /// `AlreadyExists` if the handle is already registered.
+    ///
+    pub fn add<S: PollEx + ?Sized>(&self, source: &S, token: usize, interest: Interest) -> io::Result<()> {
+        let fd = source.raw_handle();
+        let mut inner = self.lock();
+        if inner.registrations.iter().any(|registration| registration.fd == fd) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "handle is already registered"));
+        }
+
+        inner.registrations.push(Registration {
+            fd,
+            token,
+            interest,
+            armed: None,
+        });
+
+        //Armed right away, so a wait that is in progress reports the handle.
+        if inner.ring.is_some() {
+            let index = inner.registrations.len() - 1;
+            if let Err(err) = inner.arm(index).and_then(|()| inner.submit()) {
+                inner.registrations.pop();
+                return Err(err);
+            }
+        }
+
+        drop(inner);
+        Ok(())
+    }
+
+    /// Changes the token and interest of a registered handle.
+    ///
+    /// # Errors
+    /// `NotFound` if the handle is not registered.
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn modify<S: PollEx + ?Sized>(&self, source: &S, token: usize, interest: Interest) -> io::Result<()> {
+        let mut inner = self.lock();
+        let index = inner.position(source.raw_handle())?;
+        inner.disarm(index)?;
+        inner.registrations[index].token = token;
+        inner.registrations[index].interest = interest;
+        if inner.ring.is_some() {
+            inner.arm(index)?;
+            inner.submit()?;
+        }
+
+        drop(inner);
+        Ok(())
+    }
+
+    /// Removes a registered handle.
+    /// Unlike with epoll, closed handles are not removed automatically and must be removed before they are closed.
+    ///
+    /// # Errors
+    /// `NotFound` if the handle is not registered.
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn remove<S: PollEx + ?Sized>(&self, source: &S) -> io::Result<()> {
+        let mut inner = self.lock();
+        let index = inner.position(source.raw_handle())?;
+        inner.disarm(index)?;
+        //The submitted poll holds a reference to the handle that would keep a closed listener open.
+        if inner.ring.is_some() {
+            inner.submit()?;
+        }
+
+        inner.registrations.swap_remove(index);
+        drop(inner);
+        Ok(())
+    }
+
+    /// Waits until at least one registered handle is ready or the timeout elapses.
+    /// The events are stored in the buffer, the amount of events is returned.
+    ///
+    /// Zero events are returned if the timeout elapses or an operating system dependent spurious wakeup occurs.
+    ///
+    /// # Errors
+    /// Operating system and implementation-specific errors.
+    ///
+    pub fn wait(&self, events: &mut Events, timeout: Option<Duration>) -> io::Result<usize> {
+        events.clear();
+        let _waiter = self.waiter.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut inner = self.lock();
+        if inner.ring.is_none() {
+            drop(inner);
+            return self.wait_ppoll(events, timeout);
+        }
+
+        inner.arm_all()?;
+        if inner.reap(events) != 0 {
+            //Submits the polls that were armed above.
+            inner.submit()?;
+            return Ok(events.len());
+        }
+
+        match timeout {
+            Some(timeout) => inner.start_timeout(timeout)?,
+            None => inner.submit()?,
+        }
+
+        let ring = inner.ring().as_raw_fd();
+        drop(inner);
+        loop {
+            //The lock is released while blocking, so registrations can be changed in the meantime.
+            let result = enter(ring);
+            let mut inner = self.lock();
+            if let Err(err) = result {
+                inner.cancel_timeout()?;
+                return Err(err);
+            }
+
+            //Completions of removals and cancelled polls wake the wait without an event.
+            if inner.reap(events) != 0 || (timeout.is_some() && inner.timeout.is_none()) {
+                inner.cancel_timeout()?;
+                return Ok(events.len());
+            }
+
+            drop(inner);
+        }
+    }
+
+    /// Waits with ppoll, used if `io_uring` is not available.
+    /// The lock is released while blocking, handles that were removed in the meantime are not reported.
+    fn wait_ppoll(&self, events: &mut Events, timeout: Option<Duration>) -> io::Result<usize> {
+        let inner = self.lock();
+        let mut fds = Vec::with_capacity(inner.registrations.len());
+        let mut polled = Vec::with_capacity(inner.registrations.len());
+        for registration in &inner.registrations {
+            fds.push(sys::new_poll_fd(registration.fd, registration.interest)?);
+            polled.push((registration.fd, registration.token));
+        }
+
+        drop(inner);
+        if sys::poll_fds(&mut fds, timeout)? == 0 {
+            return Ok(0);
+        }
+
+        let inner = self.lock();
+        for ((handle, token), fd) in polled.into_iter().zip(fds) {
+            let registered = inner.registrations.iter().any(|registration| registration.fd == handle && registration.token == token);
+            if registered && sys::is_ready(fd) && !events.push(token, sys::readiness(fd)) {
+                break;
+            }
+        }
+
+        drop(inner);
+        Ok(events.len())
+    }
+
+    /// Locks the state, a poisoned lock is harmless because every modification is completed before it can panic.
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl std::fmt::Debug for UringPoller {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let inner = self.lock();
+        f.debug_struct("UringPoller")
+            .field("io_uring", &inner.ring.is_some())
+            .field("registrations", &inner.registrations)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Inner {
+    /// Returns the ring, only call this if `io_uring` is used.
+    fn ring(&mut self) -> &mut IoUring {
+        self.ring.as_mut().expect("Unreachable: ring used while falling back to ppoll")
+    }
+
+    /// Returns the index of the registration of a handle.
+    fn position(&self, fd: RawHandle) -> io::Result<usize> {
+        self.registrations
+            .iter()
+            .position(|registration| registration.fd == fd)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "handle is not registered"))
+    }
+
+    /// Pushes a submission, the queue is submitted first if it is full.
+    fn push(&mut self, entry: &squeue::Entry) -> io::Result<()> {
+        let ring = self.ring();
+        if unsafe { ring.submission().push(entry) }.is_ok() {
+            return Ok(());
+        }
+
+        ring.submit()?;
+        unsafe { ring.submission().push(entry) }
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "io_uring submission queue is full after submitting"))
+    }
+
+    /// Submits the queued submissions.
+    fn submit(&mut self) -> io::Result<()> {
+        self.ring().submit()?;
+        Ok(())
+    }
+
+    /// Returns the `user_data` for the next poll submission.
+    fn next_id(&mut self) -> u64 {
+        let id = self.next_id;
+        //u64 does not overflow in practice, skipping IGNORED keeps this correct anyway.
+        self.next_id = self.next_id.checked_add(1).unwrap_or(IGNORED + 1);
+        id
+    }
+
+    /// Queues the cancellation of the submitted poll of a registration, its completion is ignored.
+    fn disarm(&mut self, index: usize) -> io::Result<()> {
+        if let Some(id) = self.registrations[index].armed.take() {
+            self.push(&opcode::PollRemove::new(id).build().user_data(IGNORED))?;
+        }
+
+        Ok(())
+    }
+
+    /// Queues a poll for a registration.
+    fn arm(&mut self, index: usize) -> io::Result<()> {
+        let id = self.next_id();
+        let registration = &self.registrations[index];
+        let entry = opcode::PollAdd::new(Fd(registration.fd), interest_events(registration.interest))
+            .build()
+            .user_data(id);
+        self.push(&entry)?;
+        self.registrations[index].armed = Some(id);
+        Ok(())
+    }
+
+    /// Queues a poll for every registration that has none.
+    fn arm_all(&mut self) -> io::Result<()> {
+        for index in 0..self.registrations.len() {
+            if self.registrations[index].armed.is_none() {
+                self.arm(index)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Submits a poll of the timer with a linked timeout together with the queued submissions.
+    /// The timeout cancels the timer poll once it fires, which ends the wait.
+    fn start_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        let id = self.next_id();
+        let time = Timespec::new()
+            .sec(timeout.as_secs().min(i64::MAX.unsigned_abs()))
+            .nsec(timeout.subsec_nanos());
+        let entries = [
+            opcode::PollAdd::new(Fd(self.timer.as_raw_fd()), interest_events(Interest::READ))
+                .build()
+                .flags(squeue::Flags::IO_LINK)
+                .user_data(id),
+            opcode::LinkTimeout::new(&time).build().user_data(IGNORED),
+        ];
+
+        //A link only holds within one submission, so both entries are pushed at once.
+        let ring = self.ring();
+        if unsafe { ring.submission().push_multiple(&entries) }.is_err() {
+            ring.submit()?;
+            unsafe { ring.submission().push_multiple(&entries) }
+                .map_err(|_| io::Error::new(io::ErrorKind::Other, "io_uring submission queue is full after submitting"))?;
+        }
+
+        //The kernel copies the time during the submission.
+        ring.submit()?;
+        self.timeout = Some(id);
+        Ok(())
+    }
+
+    /// Cancels the timer poll of a wait that ended before its timeout fired.
+    fn cancel_timeout(&mut self) -> io::Result<()> {
+        if let Some(id) = self.timeout.take() {
+            self.push(&opcode::PollRemove::new(id).build().user_data(IGNORED))?;
+            self.submit()?;
+        }
+
+        Ok(())
+    }
+
+    /// Moves completed polls into the events until the buffer is full, returns the amount of events.
+    fn reap(&mut self, events: &mut Events) -> usize {
+        let Self {
+            ring,
+            registrations,
+            timeout,
+            ..
+        } = self;
+        let mut completion = ring.as_mut().expect("Unreachable: ring used while falling back to ppoll").completion();
+        while events.len() < events.capacity() {
+            let Some(entry) = completion.next() else {
+                break;
+            };
+
+            let id = entry.user_data();
+            if id == IGNORED {
+                continue;
+            }
+
+            if *timeout == Some(id) {
+                *timeout = None;
+                continue;
+            }
+
+            //Completions of cancelled polls belong to no registration.
+            if let Some(registration) = registrations.iter_mut().find(|registration| registration.armed == Some(id)) {
+                registration.armed = None;
+                events.push(registration.token, completion_readiness(&entry));
+            }
+        }
+
+        events.len()
+    }
+}
+
+/// Returns true if the kernel supports every operation that is used, probing needs Linux 5.6.
+fn is_supported(ring: &IoUring) -> bool {
+    let mut probe = Probe::new();
+    ring.submitter().register_probe(&mut probe).is_ok()
+        && [opcode::PollAdd::CODE, opcode::PollRemove::CODE, opcode::LinkTimeout::CODE]
+            .into_iter()
+            .all(|code| probe.is_supported(code))
+}
+
+/// Waits until the completion queue is not empty.
+fn enter(ring: libc::c_int) -> io::Result<()> {
+    if unsafe { libc::syscall(libc::SYS_io_uring_enter, ring, 0u32, 1u32, ENTER_GETEVENTS, std::ptr::null::<libc::c_void>(), 0usize) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Converts the result of a completed poll into readiness flags.
+fn completion_readiness(entry: &cqueue::Entry) -> Readiness {
+    let Ok(mask) = u32::try_from(entry.result()) else {
+        return if entry.result() == -libc::EBADF {
+            Readiness::INVALID
+        } else {
+            Readiness::ERROR
+        };
+    };
+
+    let mut readiness = readiness(mask);
+    if mask & u32::from(libc::POLLNVAL.unsigned_abs()) != 0 {
+        readiness |= Readiness::INVALID;
+    }
+
+    readiness
+}
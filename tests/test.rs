@@ -1,7 +1,18 @@
-use listener_poll::PollEx;
+#![allow(clippy::bool_assert_comparison)]
+
+use listener_poll::{
+    connect_happy_eyeballs, connect_with_poll, Backend, FdPoller, Interest, ListenerSet, PollAccept,
+    PollEx, PollInterrupt, PollOptions, PollOutcome, Readiness,
+};
+#[cfg(unix)]
+use listener_poll::EintrPolicy;
 use std::net::{TcpListener, TcpStream};
 #[cfg(unix)]
 use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::atomic::Ordering;
+#[cfg(unix)]
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
 
@@ -80,3 +91,1465 @@ pub fn test_unix_listen() {
     jh.join().unwrap();
     _ = std::fs::remove_file("/tmp/897987698779182378");
 }
+
+#[test]
+pub fn test_listener_set() {
+    let first = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let second = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let mut set = ListenerSet::new();
+    assert_eq!(0, set.add(&first).unwrap());
+    set.add_with_token(&second, 42).unwrap();
+    assert_eq!(2, set.len());
+
+    let time = Instant::now();
+    assert!(set.poll(Some(Duration::from_secs(2))).unwrap().is_empty());
+    assert!(time.elapsed().as_millis() >= 1800);
+
+    let laddr = second.local_addr().unwrap();
+    let jh = thread::spawn(move || {
+        let _stream = TcpStream::connect(laddr).unwrap();
+    });
+    assert_eq!(vec![42], set.poll(Some(Duration::from_secs(2))).unwrap());
+    jh.join().unwrap();
+
+    let laddr = first.local_addr().unwrap();
+    let _stream = TcpStream::connect(laddr).unwrap();
+    assert_eq!(vec![0, 42], set.poll_until_ready().unwrap());
+
+    drop(set);
+    first.accept().unwrap();
+    second.accept().unwrap();
+    assert_eq!(false, first.poll_non_blocking().unwrap());
+    assert_eq!(false, second.poll_non_blocking().unwrap());
+}
+
+#[test]
+pub fn test_poll_interrupt() {
+    let bnd = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let interrupt = PollInterrupt::new().unwrap();
+    assert_eq!(
+        PollOutcome::TimedOut,
+        bnd.poll_interruptible(Some(Duration::from_millis(100)), &interrupt).unwrap()
+    );
+
+    let remote = interrupt.clone();
+    let jh = thread::spawn(move || {
+        thread::sleep(Duration::from_secs(1));
+        remote.interrupt().unwrap();
+    });
+    let time = Instant::now();
+    assert_eq!(PollOutcome::Interrupted, bnd.poll_until_ready_interruptible(&interrupt).unwrap());
+    assert!(time.elapsed().as_millis() >= 800);
+    assert!(time.elapsed().as_millis() < 5000);
+    jh.join().unwrap();
+
+    //The interrupt stays triggered until it is reset.
+    assert_eq!(
+        PollOutcome::Interrupted,
+        bnd.poll_interruptible(Some(Duration::from_secs(2)), &interrupt).unwrap()
+    );
+    let mut set = ListenerSet::new();
+    set.add(&bnd).unwrap();
+    assert_eq!(None, set.poll_interruptible(None, &interrupt).unwrap());
+
+    interrupt.reset().unwrap();
+    let _stream = TcpStream::connect(bnd.local_addr().unwrap()).unwrap();
+    assert_eq!(Some(vec![0]), set.poll_interruptible(Some(Duration::from_secs(2)), &interrupt).unwrap());
+    assert_eq!(PollOutcome::Ready, bnd.poll_until_ready_interruptible(&interrupt).unwrap());
+}
+
+#[test]
+pub fn test_accept_timeout() {
+    let bnd = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let time = Instant::now();
+    assert!(bnd.accept_timeout(Some(Duration::from_secs(2))).unwrap().is_none());
+    assert!(time.elapsed().as_millis() >= 1800);
+    assert!(bnd.accept_timeout(Some(Duration::ZERO)).unwrap().is_none());
+
+    let laddr = bnd.local_addr().unwrap();
+    let _stream = TcpStream::connect(laddr).unwrap();
+    let (_accepted, addr) = bnd.accept_timeout(Some(Duration::from_secs(2))).unwrap().unwrap();
+    assert_eq!(laddr.ip(), addr.ip());
+
+    //Two threads race for one connection, the loser must not block past its timeout.
+    let bnd = Arc::new(bnd);
+    let _stream = TcpStream::connect(laddr).unwrap();
+    let mut handles = Vec::new();
+    for _ in 0..2 {
+        let bnd = bnd.clone();
+        handles.push(thread::spawn(move || {
+            bnd.accept_timeout(Some(Duration::from_secs(2))).unwrap().is_some()
+        }));
+    }
+    let accepted: Vec<bool> = handles.into_iter().map(|jh| jh.join().unwrap()).collect();
+    assert_eq!(1, accepted.iter().filter(|a| **a).count());
+
+    //The listener is blocking again.
+    let jh = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(500));
+        let _stream = TcpStream::connect(laddr).unwrap();
+    });
+    bnd.accept().unwrap();
+    jh.join().unwrap();
+}
+
+/// A listener that fails to switch back to blocking once.
+struct FailingRestore {
+    /// the listener.
+    listener: TcpListener,
+    /// fail the next switch to blocking.
+    fail: std::sync::atomic::AtomicBool,
+}
+
+impl PollEx for FailingRestore {
+    fn raw_handle(&self) -> listener_poll::RawHandle {
+        self.listener.raw_handle()
+    }
+}
+
+impl PollAccept for FailingRestore {
+    type Stream = TcpStream;
+    type Addr = std::net::SocketAddr;
+
+    fn accept(&self) -> std::io::Result<(Self::Stream, Self::Addr)> {
+        self.listener.accept()
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        if !nonblocking && self.fail.swap(false, Ordering::SeqCst) {
+            return Err(std::io::Error::new(std::io::ErrorKind::Other, "restore failed"));
+        }
+
+        self.listener.set_nonblocking(nonblocking)
+    }
+
+    fn set_stream_nonblocking(stream: &Self::Stream, nonblocking: bool) -> std::io::Result<()> {
+        stream.set_nonblocking(nonblocking)
+    }
+}
+
+#[test]
+pub fn test_accept_timeout_restore_failure() {
+    let bnd = FailingRestore {
+        listener: TcpListener::bind(("127.0.0.1", 0)).unwrap(),
+        fail: std::sync::atomic::AtomicBool::new(true),
+    };
+    let laddr = bnd.listener.local_addr().unwrap();
+
+    //The connection is returned although the listener could not be switched back.
+    let _stream = TcpStream::connect(laddr).unwrap();
+    assert!(bnd.accept_timeout(Some(Duration::from_secs(2))).unwrap().is_some());
+
+    //The next call switches it back before accepting and reports the error of that.
+    bnd.fail.store(true, Ordering::SeqCst);
+    let _stream = TcpStream::connect(laddr).unwrap();
+    let error = bnd.accept_timeout(Some(Duration::from_secs(2))).unwrap_err();
+    assert_eq!("restore failed", error.to_string());
+    assert!(bnd.accept_timeout(Some(Duration::from_secs(2))).unwrap().is_some());
+
+    //The listener is blocking again.
+    let jh = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(500));
+        let _stream = TcpStream::connect(laddr).unwrap();
+    });
+    bnd.listener.accept().unwrap();
+    jh.join().unwrap();
+}
+
+#[test]
+pub fn test_poll_exact() {
+    let bnd = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let time = Instant::now();
+    assert_eq!(false, bnd.poll_exact(Duration::from_secs(2)).unwrap());
+    assert!(time.elapsed() >= Duration::from_secs(2));
+
+    let time = Instant::now();
+    assert_eq!(false, bnd.poll_exact(Duration::from_micros(1500)).unwrap());
+    assert!(time.elapsed() >= Duration::from_micros(1500));
+
+    let deadline = Instant::now() + Duration::from_millis(500);
+    assert_eq!(false, bnd.poll_deadline(deadline).unwrap());
+    assert!(Instant::now() >= deadline);
+
+    let laddr = bnd.local_addr().unwrap();
+    let jh = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(500));
+        let _stream = TcpStream::connect(laddr).unwrap();
+    });
+    let time = Instant::now();
+    assert_eq!(true, bnd.poll_exact(Duration::from_secs(5)).unwrap());
+    assert!(time.elapsed() < Duration::from_secs(5));
+    jh.join().unwrap();
+}
+
+#[cfg(unix)]
+extern "C" fn noop_signal_handler(_: libc::c_int) {}
+
+/// Polls on another thread with the policy while SIGUSR1 is repeatedly sent to that thread.
+#[cfg(unix)]
+fn poll_while_signaled(policy: EintrPolicy) -> (std::io::Result<bool>, Duration) {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = noop_signal_handler as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        assert_eq!(0, libc::sigaction(libc::SIGUSR1, &action, std::ptr::null_mut()));
+    }
+
+    let done = Arc::new(AtomicBool::new(false));
+    let (tx, rx) = std::sync::mpsc::channel();
+    let (stop_tx, stop_rx) = std::sync::mpsc::channel::<()>();
+    let thread_done = done.clone();
+    let jh = thread::spawn(move || {
+        tx.send(unsafe { libc::pthread_self() }).unwrap();
+        let bnd = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let options = PollOptions::new()
+            .timeout(Some(Duration::from_secs(2)))
+            .eintr_policy(policy);
+        let time = Instant::now();
+        let result = bnd.poll_with_options(&options);
+        let elapsed = time.elapsed();
+        thread_done.store(true, Ordering::SeqCst);
+        //Stay alive until no more signals are sent to this thread.
+        stop_rx.recv().unwrap();
+        (result, elapsed)
+    });
+
+    let thread = rx.recv().unwrap();
+    while !done.load(Ordering::SeqCst) {
+        thread::sleep(Duration::from_millis(100));
+        if !done.load(Ordering::SeqCst) {
+            unsafe { libc::pthread_kill(thread, libc::SIGUSR1) };
+        }
+    }
+
+    stop_tx.send(()).unwrap();
+    jh.join().unwrap()
+}
+
+#[test]
+#[cfg(unix)]
+pub fn test_eintr_policy() {
+    let (result, elapsed) = poll_while_signaled(EintrPolicy::Retry);
+    assert_eq!(false, result.unwrap());
+    assert!(elapsed >= Duration::from_secs(2));
+
+    let (result, elapsed) = poll_while_signaled(EintrPolicy::ReturnFalse);
+    assert_eq!(false, result.unwrap());
+    assert!(elapsed < Duration::from_millis(1500));
+
+    let (result, elapsed) = poll_while_signaled(EintrPolicy::Propagate);
+    assert_eq!(std::io::ErrorKind::Interrupted, result.unwrap_err().kind());
+    assert!(elapsed < Duration::from_millis(1500));
+}
+
+#[cfg(all(unix, not(target_vendor = "apple"), not(target_os = "openbsd")))]
+static SIGUSR2_RECEIVED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(all(unix, not(target_vendor = "apple"), not(target_os = "openbsd")))]
+extern "C" fn sigusr2_handler(_: libc::c_int) {
+    SIGUSR2_RECEIVED.store(true, Ordering::SeqCst);
+}
+
+#[test]
+#[cfg(all(unix, not(target_vendor = "apple"), not(target_os = "openbsd")))]
+pub fn test_poll_with_sigmask() {
+    use listener_poll::SigSet;
+
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = sigusr2_handler as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        assert_eq!(0, libc::sigaction(libc::SIGUSR2, &action, std::ptr::null_mut()));
+    }
+
+    thread::spawn(|| {
+        let mut blocked = SigSet::empty();
+        blocked.add(libc::SIGUSR2).unwrap();
+        let mut during_poll = blocked.block_in_thread().unwrap();
+        during_poll.remove(libc::SIGUSR2).unwrap();
+        assert!(SigSet::current().unwrap().contains(libc::SIGUSR2).unwrap());
+
+        //The signal stays pending while it is blocked.
+        unsafe { libc::pthread_kill(libc::pthread_self(), libc::SIGUSR2) };
+        let bnd = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        assert_eq!(false, bnd.poll(Some(Duration::from_millis(100))).unwrap());
+        assert!(!SIGUSR2_RECEIVED.load(Ordering::SeqCst));
+
+        //It is delivered as soon as the poll unblocks it.
+        let time = Instant::now();
+        let err = bnd.poll_with_sigmask(Some(Duration::from_secs(2)), &during_poll).unwrap_err();
+        assert_eq!(std::io::ErrorKind::Interrupted, err.kind());
+        assert!(time.elapsed() < Duration::from_secs(1));
+        assert!(SIGUSR2_RECEIVED.load(Ordering::SeqCst));
+
+        //The original mask is restored after the poll.
+        assert!(SigSet::current().unwrap().contains(libc::SIGUSR2).unwrap());
+        let time = Instant::now();
+        assert_eq!(false, bnd.poll_with_sigmask(Some(Duration::from_millis(500)), &during_poll).unwrap());
+        assert!(time.elapsed() >= Duration::from_millis(400));
+    })
+    .join()
+    .unwrap();
+}
+
+/// A handle that is never open.
+#[cfg(unix)]
+struct ClosedFd;
+
+#[cfg(unix)]
+impl PollEx for ClosedFd {
+    fn raw_handle(&self) -> listener_poll::RawHandle {
+        //Way above any file descriptor this test process opens.
+        1_000_000
+    }
+}
+
+#[test]
+pub fn test_poll_events() {
+    let bnd = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    assert_eq!(Readiness::EMPTY, bnd.poll_events(Some(Duration::from_millis(100))).unwrap());
+
+    let _stream = TcpStream::connect(bnd.local_addr().unwrap()).unwrap();
+    let readiness = bnd.poll_events(Some(Duration::from_secs(2))).unwrap();
+    assert!(readiness.is_readable());
+    assert!(!readiness.is_invalid());
+
+    #[cfg(unix)]
+    {
+        assert_eq!(Readiness::INVALID, ClosedFd.poll_events(None).unwrap());
+        //poll itself cannot tell the closed handle apart from a ready listener.
+        assert_eq!(true, ClosedFd.poll_non_blocking().unwrap());
+
+        let mut set = ListenerSet::new();
+        set.add(&bnd).unwrap();
+        set.add(&ClosedFd).unwrap();
+        assert_eq!(
+            vec![(0, Readiness::READABLE), (1, Readiness::INVALID)],
+            set.poll_events(Some(Duration::from_secs(2))).unwrap()
+        );
+    }
+}
+
+#[test]
+pub fn test_fd_poller() {
+    let socket = std::net::UdpSocket::bind(("127.0.0.1", 0)).unwrap();
+    let sender = std::net::UdpSocket::bind(("127.0.0.1", 0)).unwrap();
+    let readable = FdPoller::new(&socket);
+    let time = Instant::now();
+    assert_eq!(false, readable.poll(Some(Duration::from_secs(1))).unwrap());
+    assert!(time.elapsed().as_millis() >= 800);
+    assert_eq!(true, FdPoller::with_interest(&socket, Interest::WRITE).poll_non_blocking().unwrap());
+
+    sender.send_to(&[1, 2, 3], socket.local_addr().unwrap()).unwrap();
+    assert_eq!(true, readable.poll(Some(Duration::from_secs(2))).unwrap());
+    let mut buf = [0u8; 16];
+    assert_eq!(3, socket.recv(&mut buf).unwrap());
+    assert_eq!(false, readable.poll_non_blocking().unwrap());
+
+    #[cfg(unix)]
+    {
+        use std::io::Write;
+        let (a, mut b) = UnixStream::pair().unwrap();
+        let mut poller = FdPoller::new(a);
+        assert_eq!(false, poller.poll_non_blocking().unwrap());
+        b.write_all(&[1]).unwrap();
+        assert_eq!(Readiness::READABLE, poller.poll_events(Some(Duration::from_secs(2))).unwrap());
+
+        poller.set_interest(Interest::READ | Interest::WRITE);
+        assert_eq!(
+            Readiness::READABLE | Readiness::WRITABLE,
+            poller.poll_events(Some(Duration::from_secs(2))).unwrap()
+        );
+
+        drop(b);
+        assert!(poller.poll_events(Some(Duration::from_secs(2))).unwrap().is_hangup());
+        let _a: UnixStream = poller.into_inner();
+    }
+}
+
+#[test]
+pub fn test_poll_writable() {
+    let bnd = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let mut stream = TcpStream::connect(bnd.local_addr().unwrap()).unwrap();
+    let (peer, _) = bnd.accept().unwrap();
+
+    assert_eq!(true, stream.poll_writable(Some(Duration::from_secs(2))).unwrap());
+    assert_eq!(false, stream.poll_non_blocking().unwrap());
+    assert_eq!(
+        Readiness::WRITABLE,
+        stream
+            .poll_interest(Interest::READ | Interest::WRITE, Some(Duration::from_secs(2)))
+            .unwrap()
+    );
+
+    //Fill the send buffer until the peer must read first.
+    use std::io::Write;
+    stream.set_nonblocking(true).unwrap();
+    let chunk = [0u8; 65536];
+    loop {
+        match stream.write(&chunk) {
+            Ok(_) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => break,
+            Err(err) => panic!("{err}"),
+        }
+    }
+
+    let time = Instant::now();
+    assert_eq!(false, stream.poll_writable(Some(Duration::from_secs(1))).unwrap());
+    assert!(time.elapsed().as_millis() >= 800);
+    assert_eq!(true, peer.poll(Some(Duration::from_secs(2))).unwrap());
+}
+
+#[test]
+pub fn test_connect_with_poll() {
+    let bnd = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let laddr = bnd.local_addr().unwrap();
+    let stream = connect_with_poll(laddr, Some(Duration::from_secs(2)), None).unwrap();
+    assert_eq!(laddr, stream.peer_addr().unwrap());
+    assert_eq!(true, bnd.poll(Some(Duration::from_secs(2))).unwrap());
+
+    let closed = TcpListener::bind(("127.0.0.1", 0)).unwrap().local_addr().unwrap();
+    let err = connect_with_poll(closed, Some(Duration::from_secs(2)), None).unwrap_err();
+    assert_eq!(std::io::ErrorKind::ConnectionRefused, err.kind());
+
+    let addrs = [closed, laddr];
+    let stream = connect_with_poll(&addrs[..], Some(Duration::from_secs(2)), None).unwrap();
+    assert_eq!(laddr, stream.peer_addr().unwrap());
+    let stream = connect_happy_eyeballs(&addrs[..], Duration::from_millis(250), Some(Duration::from_secs(2)), None).unwrap();
+    assert_eq!(laddr, stream.peer_addr().unwrap());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+pub fn test_connect_with_poll_pending() {
+    use std::os::fd::AsRawFd;
+
+    //A full backlog makes the kernel drop further connection attempts, so they stay pending.
+    let bnd = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    assert_eq!(0, unsafe { libc::listen(bnd.as_raw_fd(), 0) });
+    let laddr = bnd.local_addr().unwrap();
+    let mut connected = Vec::new();
+    let mut timed_out = false;
+    for _ in 0..16 {
+        match connect_with_poll(laddr, Some(Duration::from_millis(500)), None) {
+            Ok(stream) => connected.push(stream),
+            Err(err) => {
+                assert_eq!(std::io::ErrorKind::TimedOut, err.kind());
+                timed_out = true;
+                break;
+            }
+        }
+    }
+    assert!(timed_out);
+
+    let interrupt = PollInterrupt::new().unwrap();
+    let remote = interrupt.clone();
+    let jh = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(500));
+        remote.interrupt().unwrap();
+    });
+    let time = Instant::now();
+    let err = connect_with_poll(laddr, None, Some(&interrupt)).unwrap_err();
+    assert_eq!(std::io::ErrorKind::Other, err.kind());
+    assert_eq!("connect was cancelled by PollInterrupt", err.to_string());
+    assert!(time.elapsed() < Duration::from_secs(5));
+    jh.join().unwrap();
+
+    //Signals do not abort the connect, it runs until the timeout.
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = noop_signal_handler as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        assert_eq!(0, libc::sigaction(libc::SIGUSR1, &action, std::ptr::null_mut()));
+    }
+
+    let done = Arc::new(AtomicBool::new(false));
+    let thread_done = Arc::clone(&done);
+    let (tx, rx) = std::sync::mpsc::channel();
+    let (stop_tx, stop_rx) = std::sync::mpsc::channel::<()>();
+    let jh = thread::spawn(move || {
+        tx.send(unsafe { libc::pthread_self() }).unwrap();
+        let time = Instant::now();
+        let result = connect_with_poll(laddr, Some(Duration::from_secs(1)), None);
+        let elapsed = time.elapsed();
+        thread_done.store(true, Ordering::SeqCst);
+        stop_rx.recv().unwrap();
+        (result, elapsed)
+    });
+
+    let thread = rx.recv().unwrap();
+    while !done.load(Ordering::SeqCst) {
+        thread::sleep(Duration::from_millis(50));
+        if !done.load(Ordering::SeqCst) {
+            unsafe { libc::pthread_kill(thread, libc::SIGUSR1) };
+        }
+    }
+
+    stop_tx.send(()).unwrap();
+    let (result, elapsed) = jh.join().unwrap();
+    assert_eq!(std::io::ErrorKind::TimedOut, result.unwrap_err().kind());
+    assert!(elapsed >= Duration::from_millis(900));
+
+    let time = Instant::now();
+    let err = connect_happy_eyeballs(laddr, Duration::from_millis(100), Some(Duration::from_secs(1)), None).unwrap_err();
+    assert_eq!(std::io::ErrorKind::TimedOut, err.kind());
+    assert!(time.elapsed() >= Duration::from_millis(900));
+}
+
+#[test]
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn test_epoll_poller() {
+    use listener_poll::{EpollPoller, Events};
+
+    let first = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let second = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let poller = EpollPoller::new().unwrap();
+    poller.add(&first, 1, Interest::READ).unwrap();
+    poller.add(&second, 2, Interest::READ).unwrap();
+    assert_eq!(std::io::ErrorKind::AlreadyExists, poller.add(&first, 1, Interest::READ).unwrap_err().kind());
+
+    let mut events = Events::with_capacity(8);
+    let time = Instant::now();
+    assert_eq!(0, poller.wait(&mut events, Some(Duration::from_secs(2))).unwrap());
+    assert!(time.elapsed().as_millis() >= 1800);
+    assert!(events.is_empty());
+
+    let time = Instant::now();
+    assert_eq!(0, poller.wait(&mut events, Some(Duration::from_micros(200))).unwrap());
+    assert!(time.elapsed() >= Duration::from_micros(200));
+    assert!(time.elapsed().as_millis() < 500);
+
+    let _stream = TcpStream::connect(second.local_addr().unwrap()).unwrap();
+    assert_eq!(1, poller.wait(&mut events, Some(Duration::from_secs(2))).unwrap());
+    let event = events.iter().next().unwrap();
+    assert_eq!(2, event.token());
+    assert!(event.readiness().is_readable());
+
+    poller.modify(&second, 3, Interest::READ).unwrap();
+    assert_eq!(1, poller.wait(&mut events, None).unwrap());
+    assert_eq!(3, events.iter().next().unwrap().token());
+
+    poller.remove(&second).unwrap();
+    assert_eq!(0, poller.wait(&mut events, Some(Duration::from_millis(100))).unwrap());
+    assert_eq!(std::io::ErrorKind::NotFound, poller.remove(&second).unwrap_err().kind());
+}
+
+/// Lets four threads wait for connections on one shared listener and returns how often they were woken.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn count_wakeups(exclusive: bool, connections: usize) -> usize {
+    use listener_poll::ExclusivePoller;
+    use std::sync::atomic::AtomicUsize;
+
+    let bnd = Arc::new(TcpListener::bind(("127.0.0.1", 0)).unwrap());
+    let laddr = bnd.local_addr().unwrap();
+    let stop = Arc::new(AtomicBool::new(false));
+    let wakeups = Arc::new(AtomicUsize::new(0));
+    let accepted = Arc::new(AtomicUsize::new(0));
+
+    let workers: Vec<_> = (0..4)
+        .map(|_| {
+            let bnd = Arc::clone(&bnd);
+            let stop = Arc::clone(&stop);
+            let wakeups = Arc::clone(&wakeups);
+            let accepted = Arc::clone(&accepted);
+            thread::spawn(move || {
+                let mut poller = ExclusivePoller::new(&*bnd).unwrap();
+                while !stop.load(Ordering::SeqCst) {
+                    let woken = if exclusive {
+                        poller.poll(Some(Duration::from_millis(50))).unwrap()
+                    } else {
+                        bnd.poll(Some(Duration::from_millis(50))).unwrap()
+                    };
+                    if !woken {
+                        continue;
+                    }
+
+                    wakeups.fetch_add(1, Ordering::SeqCst);
+                    //Stands in for the time a busy server needs until the woken thread runs and accepts.
+                    thread::sleep(Duration::from_millis(5));
+                    if bnd.accept_timeout(Some(Duration::ZERO)).unwrap().is_some() {
+                        accepted.fetch_add(1, Ordering::SeqCst);
+                    }
+                }
+            })
+        })
+        .collect();
+
+    thread::sleep(Duration::from_millis(200));
+    let mut streams = Vec::new();
+    for _ in 0..connections {
+        streams.push(TcpStream::connect(laddr).unwrap());
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    let time = Instant::now();
+    while accepted.load(Ordering::SeqCst) < connections && time.elapsed() < Duration::from_secs(5) {
+        thread::sleep(Duration::from_millis(10));
+    }
+    stop.store(true, Ordering::SeqCst);
+    for jh in workers {
+        jh.join().unwrap();
+    }
+
+    assert_eq!(connections, accepted.load(Ordering::SeqCst));
+    wakeups.load(Ordering::SeqCst)
+}
+
+#[test]
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn test_exclusive_poller_wakeups() {
+    let shared = count_wakeups(false, 20);
+    let exclusive = count_wakeups(true, 20);
+    assert!(exclusive < shared, "exclusive epoll woke {exclusive} times, shared poll {shared} times");
+    //Every connection wakes one thread, a second wakeup can race with the accept of the first.
+    assert!(exclusive <= 40, "exclusive epoll woke {exclusive} times for 20 connections");
+}
+
+#[test]
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn test_drain_accept_edge_triggered() {
+    use listener_poll::{EpollPoller, Events};
+
+    let bnd = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let laddr = bnd.local_addr().unwrap();
+    let poller = EpollPoller::new().unwrap();
+    poller.add_edge_triggered(&bnd, 5, Interest::READ).unwrap();
+    let mut events = Events::with_capacity(8);
+
+    let mut streams = Vec::new();
+    for _ in 0..10 {
+        streams.push(TcpStream::connect(laddr).unwrap());
+    }
+
+    assert_eq!(1, poller.wait(&mut events, Some(Duration::from_secs(2))).unwrap());
+    assert_eq!(5, events.iter().next().unwrap().token());
+    let mut accepted = Vec::new();
+    assert_eq!(10, bnd.drain_accept(|stream, addr| accepted.push((stream, addr))).unwrap());
+    assert_eq!(10, accepted.len());
+    for (stream, _) in &accepted {
+        use std::os::fd::AsRawFd;
+        assert_eq!(0, unsafe { libc::fcntl(stream.as_raw_fd(), libc::F_GETFL) } & libc::O_NONBLOCK);
+    }
+
+    //Edge-triggered, nothing changed since the last wait.
+    assert_eq!(0, poller.wait(&mut events, Some(Duration::from_millis(200))).unwrap());
+    assert_eq!(0, bnd.drain_accept(|_, _| panic!("no connection is pending")).unwrap());
+
+    let _stream = TcpStream::connect(laddr).unwrap();
+    assert_eq!(1, poller.wait(&mut events, Some(Duration::from_secs(2))).unwrap());
+    assert_eq!(1, bnd.drain_accept(|_, _| {}).unwrap());
+
+    //The listener is blocking again.
+    let time = Instant::now();
+    assert_eq!(false, bnd.poll(Some(Duration::from_millis(200))).unwrap());
+    assert!(time.elapsed().as_millis() >= 150);
+}
+
+#[test]
+#[cfg(all(feature = "io-uring", any(target_os = "linux", target_os = "android")))]
+pub fn test_uring_poller() {
+    use listener_poll::{Events, UringPoller};
+
+    let first = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let second = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let poller = UringPoller::new().unwrap();
+    //Without io_uring the same assertions run against the ppoll fallback.
+    assert_eq!(Backend::IoUring.is_available(), poller.is_io_uring());
+    assert!(format!("{poller:?}").contains(&format!("io_uring: {}", poller.is_io_uring())));
+    poller.add(&first, 1, Interest::READ).unwrap();
+    poller.add(&second, 2, Interest::READ).unwrap();
+    assert_eq!(std::io::ErrorKind::AlreadyExists, poller.add(&first, 1, Interest::READ).unwrap_err().kind());
+
+    let mut events = Events::with_capacity(8);
+    let time = Instant::now();
+    assert_eq!(0, poller.wait(&mut events, Some(Duration::from_secs(2))).unwrap());
+    assert!(time.elapsed().as_millis() >= 1800);
+
+    let time = Instant::now();
+    assert_eq!(0, poller.wait(&mut events, Some(Duration::from_micros(200))).unwrap());
+    assert!(time.elapsed() >= Duration::from_micros(200));
+    assert!(time.elapsed().as_millis() < 500);
+
+    let _stream = TcpStream::connect(second.local_addr().unwrap()).unwrap();
+    assert_eq!(1, poller.wait(&mut events, Some(Duration::from_secs(2))).unwrap());
+    let event = events.iter().next().unwrap();
+    assert_eq!(2, event.token());
+    assert!(event.readiness().is_readable());
+
+    //Level-triggered, the connection was not accepted.
+    assert_eq!(1, poller.wait(&mut events, None).unwrap());
+    assert_eq!(2, events.iter().next().unwrap().token());
+
+    poller.modify(&second, 3, Interest::READ).unwrap();
+    assert_eq!(1, poller.wait(&mut events, None).unwrap());
+    assert_eq!(3, events.iter().next().unwrap().token());
+
+    poller.remove(&second).unwrap();
+    assert_eq!(0, poller.wait(&mut events, Some(Duration::from_millis(100))).unwrap());
+    assert_eq!(std::io::ErrorKind::NotFound, poller.remove(&second).unwrap_err().kind());
+
+    let _stream = TcpStream::connect(first.local_addr().unwrap()).unwrap();
+    assert_eq!(1, poller.wait(&mut events, Some(Duration::from_secs(2))).unwrap());
+    assert_eq!(1, events.iter().next().unwrap().token());
+
+    //A removed and dropped listener releases its port without another wait.
+    let third = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let addr = third.local_addr().unwrap();
+    poller.add(&third, 4, Interest::READ).unwrap();
+    assert_eq!(1, poller.wait(&mut events, Some(Duration::from_millis(10))).unwrap());
+    poller.remove(&third).unwrap();
+    drop(third);
+    let time = Instant::now();
+    while TcpListener::bind(addr).is_err() {
+        assert!(time.elapsed() < Duration::from_secs(2), "the removed listener is still open");
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    //Registrations change while another thread waits.
+    poller.remove(&first).unwrap();
+    let poller = Arc::new(poller);
+    let waiter = Arc::clone(&poller);
+    let wait = thread::spawn(move || {
+        let mut events = Events::with_capacity(8);
+        let time = Instant::now();
+        waiter.wait(&mut events, Some(Duration::from_secs(2))).unwrap();
+        (events.iter().map(|event| event.token()).collect::<Vec<_>>(), time.elapsed())
+    });
+
+    thread::sleep(Duration::from_millis(100));
+    let time = Instant::now();
+    poller.add(&first, 5, Interest::READ).unwrap();
+    assert!(time.elapsed() < Duration::from_millis(500));
+    let (tokens, elapsed) = wait.join().unwrap();
+    if poller.is_io_uring() {
+        //The wait in progress reports the listener that was added.
+        assert_eq!(vec![5], tokens);
+        assert!(elapsed < Duration::from_millis(1500));
+    } else {
+        assert!(tokens.is_empty());
+    }
+}
+
+#[test]
+pub fn test_backends() {
+    let available = Backend::available();
+    assert!(available.contains(&Backend::native()));
+    assert!(available.contains(&Backend::Poll));
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    assert!(available.contains(&Backend::Epoll));
+
+    for backend in available {
+        let bnd = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let other = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let mut set = ListenerSet::with_backend(backend).unwrap();
+        assert_eq!(backend, set.backend());
+        set.add_with_token(&bnd, 1).unwrap();
+        set.add_with_token(&other, 2).unwrap();
+
+        let time = Instant::now();
+        assert!(set.poll(Some(Duration::from_millis(500))).unwrap().is_empty(), "{backend:?}");
+        assert!(time.elapsed().as_millis() >= 400, "{backend:?}");
+
+        let options = PollOptions::new().timeout(Some(Duration::from_millis(100))).backend(backend);
+        assert_eq!(false, bnd.poll_with_options(&options).unwrap(), "{backend:?}");
+
+        let _stream = TcpStream::connect(bnd.local_addr().unwrap()).unwrap();
+        assert_eq!(vec![1], set.poll(Some(Duration::from_secs(2))).unwrap(), "{backend:?}");
+        let events = set.poll_events(Some(Duration::from_secs(2))).unwrap();
+        assert_eq!(1, events.len(), "{backend:?}");
+        assert!(events[0].1.is_readable(), "{backend:?}");
+        assert_eq!(true, bnd.poll_with_options(&options).unwrap(), "{backend:?}");
+
+        let interrupt = PollInterrupt::new().unwrap();
+        interrupt.interrupt().unwrap();
+        assert_eq!(None, set.poll_interruptible(Some(Duration::from_secs(2)), &interrupt).unwrap(), "{backend:?}");
+    }
+
+    let unavailable = [Backend::Poll, Backend::PPoll, Backend::Epoll, Backend::IoUring]
+        .into_iter()
+        .find(|backend| !backend.is_available());
+    if let Some(backend) = unavailable {
+        assert_eq!(std::io::ErrorKind::Unsupported, ListenerSet::with_backend(backend).err().unwrap().kind());
+    }
+}
+
+#[test]
+#[cfg(unix)]
+pub fn test_select_backend() {
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+    assert!(Backend::Select.is_available());
+    let mut set = ListenerSet::with_backend(Backend::Select).unwrap();
+    set.add_with_token(&ClosedFd, 9).unwrap();
+    assert_eq!(std::io::ErrorKind::InvalidInput, set.poll(Some(Duration::ZERO)).unwrap_err().kind());
+
+    let bnd = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let high = unsafe { libc::fcntl(bnd.as_raw_fd(), libc::F_DUPFD_CLOEXEC, 4096) };
+    if high < 0 {
+        //The open file limit of the test process is too low.
+        return;
+    }
+
+    let high = FdPoller::new(unsafe { OwnedFd::from_raw_fd(high) });
+    let mut set = ListenerSet::with_backend(Backend::Select).unwrap();
+    set.add(&high).unwrap();
+    assert_eq!(std::io::ErrorKind::InvalidInput, set.poll(Some(Duration::ZERO)).unwrap_err().kind());
+    let options = PollOptions::new().timeout(Some(Duration::ZERO)).backend(Backend::Select);
+    assert_eq!(std::io::ErrorKind::InvalidInput, high.poll_with_options(&options).unwrap_err().kind());
+}
+
+#[test]
+#[cfg(feature = "async")]
+pub fn test_async_listener() {
+    use listener_poll::AsyncListener;
+
+    let listener = AsyncListener::new(TcpListener::bind(("127.0.0.1", 0)).unwrap()).unwrap();
+    let addr = listener.get_ref().local_addr().unwrap();
+    let connector = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(500));
+        TcpStream::connect(addr).unwrap()
+    });
+
+    let time = Instant::now();
+    let (stream, _) = futures_executor::block_on(listener.accept()).unwrap();
+    assert!(time.elapsed().as_millis() >= 400);
+    assert_eq!(connector.join().unwrap().local_addr().unwrap(), stream.peer_addr().unwrap());
+
+    //A dropped future must not keep its registration.
+    drop(listener.accept());
+
+    let connector = thread::spawn(move || {
+        for _ in 0..3 {
+            thread::sleep(Duration::from_millis(100));
+            drop(TcpStream::connect(addr).unwrap());
+        }
+    });
+
+    let accepted = futures_executor::block_on_stream(listener.incoming()).take(3).map(Result::unwrap).count();
+    assert_eq!(3, accepted);
+    connector.join().unwrap();
+
+    let listener = listener.into_inner().unwrap();
+    let _stream = TcpStream::connect(addr).unwrap();
+    listener.accept().unwrap();
+}
+
+#[test]
+#[cfg(feature = "tokio")]
+pub fn test_into_tokio() {
+    use listener_poll::IntoTokio;
+
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_io().build().unwrap();
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let _first = TcpStream::connect(addr).unwrap();
+    assert!(listener.poll(Some(Duration::from_secs(2))).unwrap());
+
+    //The pending connection survives the conversion.
+    let listener = runtime.block_on(async {
+        let listener = listener.into_tokio().unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        assert_eq!(addr, stream.local_addr().unwrap());
+        listener
+    });
+
+    let listener = TcpListener::from_tokio(listener).unwrap();
+    assert!(!listener.poll(Some(Duration::from_millis(10))).unwrap());
+    let _second = TcpStream::connect(addr).unwrap();
+    let (stream, _) = listener.accept_timeout(Some(Duration::from_secs(2))).unwrap().unwrap();
+    assert_eq!(addr, stream.local_addr().unwrap());
+
+    //Blocking again.
+    let (stream, _) = thread::scope(|scope| {
+        scope.spawn(|| {
+            thread::sleep(Duration::from_millis(100));
+            TcpStream::connect(addr).unwrap()
+        });
+        listener.accept().unwrap()
+    });
+    assert_eq!(addr, stream.local_addr().unwrap());
+}
+
+#[test]
+#[cfg(all(feature = "tokio", unix))]
+pub fn test_into_tokio_unix() {
+    use listener_poll::IntoTokio;
+
+    let path = std::env::temp_dir().join(format!("listener_poll_tokio_{}.sock", std::process::id()));
+    _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+    let _first = UnixStream::connect(&path).unwrap();
+
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_io().build().unwrap();
+    let listener = runtime.block_on(async {
+        let listener = listener.into_tokio().unwrap();
+        listener.accept().await.unwrap();
+        listener
+    });
+
+    let listener = UnixListener::from_tokio(listener).unwrap();
+    assert!(!listener.poll(Some(Duration::from_millis(10))).unwrap());
+    let _second = UnixStream::connect(&path).unwrap();
+    assert!(listener.accept_timeout(Some(Duration::from_secs(2))).unwrap().is_some());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+#[cfg(all(feature = "mio", unix))]
+pub fn test_mio_source() {
+    use listener_poll::FdPoller;
+    use mio::{Events, Interest, Poll, Token};
+
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let mut source = FdPoller::new(&listener);
+    let mut poll = Poll::new().unwrap();
+    poll.registry().register(&mut source, Token(3), Interest::READABLE).unwrap();
+
+    let mut events = Events::with_capacity(8);
+    poll.poll(&mut events, Some(Duration::from_millis(10))).unwrap();
+    assert!(events.is_empty());
+
+    let _stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    poll.poll(&mut events, Some(Duration::from_secs(2))).unwrap();
+    let event = events.iter().next().unwrap();
+    assert_eq!(Token(3), event.token());
+    assert!(event.is_readable());
+
+    //The same listener can still be polled without mio.
+    assert!(source.poll(Some(Duration::from_secs(2))).unwrap());
+    poll.registry().deregister(&mut source).unwrap();
+}
+
+#[test]
+pub fn test_accept_loop() {
+    use listener_poll::AcceptLoop;
+    use std::io::{Read, Write};
+    use std::sync::atomic::AtomicUsize;
+
+    let first = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let second = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let addrs = [first.local_addr().unwrap(), second.local_addr().unwrap()];
+
+    let handled = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&handled);
+    let server = AcceptLoop::new(move |mut stream: TcpStream, _| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        stream.write_all(b"x").unwrap();
+    })
+    .listener(first)
+    .listener(second)
+    .workers(3)
+    .spawn()
+    .unwrap();
+
+    for addr in addrs.iter().cycle().take(10) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut buf = [0u8; 1];
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(b"x", &buf);
+    }
+
+    assert_eq!(10, handled.load(Ordering::SeqCst));
+    assert!(!server.is_finished());
+
+    let time = Instant::now();
+    server.shutdown().unwrap();
+    server.join().unwrap();
+    assert!(time.elapsed().as_millis() < 1000);
+    assert!(TcpStream::connect(addrs[0]).is_err());
+
+    //A panicking handler does not stop its worker.
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = AcceptLoop::new(|mut stream: TcpStream, _| {
+        let mut buf = [0u8; 1];
+        stream.read_exact(&mut buf).unwrap();
+        assert_ne!(b'p', buf[0]);
+        stream.write_all(&buf).unwrap();
+    })
+    .listener(listener)
+    .workers(1)
+    .spawn()
+    .unwrap();
+
+    TcpStream::connect(addr).unwrap().write_all(b"p").unwrap();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"o").unwrap();
+    let mut buf = [0u8; 1];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(b"o", &buf);
+    server.shutdown().unwrap();
+    server.join().unwrap();
+
+    //Shutdown wakes the accept thread while the only worker is busy and the queue is full.
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (release, blocked) = std::sync::mpsc::channel::<()>();
+    let blocked = std::sync::Mutex::new(blocked);
+    let (started_tx, started) = std::sync::mpsc::channel::<()>();
+    let started_tx = std::sync::Mutex::new(started_tx);
+    let server = AcceptLoop::new(move |_: TcpStream, _| {
+        started_tx.lock().unwrap().send(()).unwrap();
+        blocked.lock().unwrap().recv().unwrap();
+    })
+    .listener(listener)
+    .workers(1)
+    .queue_capacity(1)
+    .spawn()
+    .unwrap();
+
+    let _streams = (0..4).map(|_| TcpStream::connect(addr).unwrap()).collect::<Vec<_>>();
+    started.recv_timeout(Duration::from_secs(2)).unwrap();
+    thread::sleep(Duration::from_millis(100));
+    server.shutdown().unwrap();
+    let time = Instant::now();
+    while !server.is_finished() {
+        assert!(time.elapsed() < Duration::from_secs(2), "the accept thread did not wake up");
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    //The queued connection is still handled.
+    release.send(()).unwrap();
+    started.recv_timeout(Duration::from_secs(2)).unwrap();
+    release.send(()).unwrap();
+    server.join().unwrap();
+
+    let error = AcceptLoop::<TcpListener, _>::new(|_, _| {}).spawn().unwrap_err();
+    assert_eq!(std::io::ErrorKind::InvalidInput, error.kind());
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let error = AcceptLoop::new(|_: TcpStream, _| {}).listener(listener).workers(0).spawn().unwrap_err();
+    assert_eq!(std::io::ErrorKind::InvalidInput, error.kind());
+}
+
+#[test]
+pub fn test_accept_loop_shutdown_graceful() {
+    use listener_poll::AcceptLoop;
+    use std::io::{Read, Write};
+    use std::sync::atomic::AtomicUsize;
+
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handled = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&handled);
+    let server = AcceptLoop::new(move |mut stream: TcpStream, _| {
+        thread::sleep(Duration::from_millis(50));
+        stream.write_all(b"x").unwrap();
+        counter.fetch_add(1, Ordering::SeqCst);
+    })
+    .listener(listener)
+    .workers(2)
+    .drain_backlog(true)
+    .spawn()
+    .unwrap();
+
+    let streams = (0..6).map(|_| TcpStream::connect(addr).unwrap()).collect::<Vec<_>>();
+    thread::sleep(Duration::from_millis(50));
+    let report = server.shutdown_graceful(Duration::from_secs(10)).unwrap();
+    assert!(report.is_complete());
+    assert_eq!(0, report.running());
+    assert_eq!(6, handled.load(Ordering::SeqCst));
+    for mut stream in streams {
+        let mut buf = [0u8; 1];
+        stream.read_exact(&mut buf).unwrap();
+    }
+
+    //The deadline expires while one handler runs and one connection waits for the only worker.
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = AcceptLoop::new(|mut stream: TcpStream, _| {
+        thread::sleep(Duration::from_millis(1000));
+        stream.write_all(b"x").unwrap();
+    })
+    .listener(listener)
+    .workers(1)
+    .spawn()
+    .unwrap();
+
+    let mut running = TcpStream::connect(addr).unwrap();
+    thread::sleep(Duration::from_millis(100));
+    let mut abandoned = TcpStream::connect(addr).unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    let time = Instant::now();
+    let report = server.shutdown_graceful(Duration::from_millis(200)).unwrap();
+    assert!(time.elapsed().as_millis() >= 150);
+    assert!(time.elapsed().as_millis() < 800);
+    assert!(!report.is_complete());
+    assert_eq!(1, report.running());
+    assert_eq!(1, report.abandoned());
+    assert!(TcpStream::connect(addr).is_err());
+
+    let mut buf = Vec::new();
+    running.read_to_end(&mut buf).unwrap();
+    assert_eq!(b"x", buf.as_slice());
+    buf.clear();
+    abandoned.read_to_end(&mut buf).unwrap();
+    assert!(buf.is_empty());
+}
+
+#[test]
+#[cfg(unix)]
+pub fn test_socket_activation() {
+    use listener_poll::{listen_fds, ActivatedListener};
+    use std::os::fd::AsRawFd;
+    use std::os::unix::process::CommandExt;
+
+    if std::env::var_os("LISTEN_POLL_ACTIVATION_CHILD").is_some() {
+        //Not meant for this process.
+        std::env::set_var("LISTEN_PID", (std::process::id() + 1).to_string());
+        assert!(listen_fds(false).unwrap().is_empty());
+        std::env::set_var("LISTEN_PID", std::process::id().to_string());
+
+        //fd 5 is a udp socket.
+        std::env::set_var("LISTEN_FDS", "3");
+        assert_eq!(std::io::ErrorKind::InvalidInput, listen_fds(false).unwrap_err().kind());
+        std::env::set_var("LISTEN_FDS", "2");
+        std::env::set_var("LISTEN_FDNAMES", "web");
+        assert_eq!(std::io::ErrorKind::InvalidData, listen_fds(false).unwrap_err().kind());
+        std::env::set_var("LISTEN_FDNAMES", "web:admin");
+
+        let mut listeners = listen_fds(true).unwrap();
+        assert!(std::env::var_os("LISTEN_FDS").is_none());
+        assert_eq!(2, listeners.len());
+
+        let (name, listener) = listeners.remove(0);
+        assert_eq!("web", name);
+        assert_eq!(3, listener.raw_handle());
+        let ActivatedListener::Tcp(tcp) = listener else {
+            panic!("fd 3 is not a tcp listener");
+        };
+        assert_eq!(std::env::var("LISTEN_POLL_TCP").unwrap(), tcp.local_addr().unwrap().to_string());
+        let _stream = TcpStream::connect(tcp.local_addr().unwrap()).unwrap();
+        assert!(tcp.accept_timeout(Some(Duration::from_secs(2))).unwrap().is_some());
+
+        let (name, listener) = listeners.remove(0);
+        assert_eq!("admin", name);
+        let ActivatedListener::Unix(listener) = listener else {
+            panic!("fd 4 is not a unix listener");
+        };
+        assert!(!listener.poll(Some(Duration::from_millis(10))).unwrap());
+        assert_eq!(libc::FD_CLOEXEC, unsafe { libc::fcntl(4, libc::F_GETFD) });
+
+        //The handles are owned now.
+        std::env::set_var("LISTEN_PID", std::process::id().to_string());
+        std::env::set_var("LISTEN_FDS", "2");
+        assert!(listen_fds(true).unwrap().is_empty());
+
+        //Closed handles are not checked again.
+        drop(tcp);
+        drop(listener);
+        std::env::set_var("LISTEN_PID", std::process::id().to_string());
+        std::env::set_var("LISTEN_FDS", "2");
+        assert!(listen_fds(true).unwrap().is_empty());
+        return;
+    }
+
+    let tcp = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let path = std::env::temp_dir().join(format!("listener_poll_activation_{}.sock", std::process::id()));
+    _ = std::fs::remove_file(&path);
+    let unix = UnixListener::bind(&path).unwrap();
+    let udp = std::net::UdpSocket::bind(("127.0.0.1", 0)).unwrap();
+
+    //Moved above the target fds so the dup2 calls of the child cannot overwrite a source.
+    let sources = [tcp.as_raw_fd(), unix.as_raw_fd(), udp.as_raw_fd()]
+        .map(|fd| unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 100) });
+    assert!(sources.iter().all(|fd| *fd >= 100));
+
+    let mut command = std::process::Command::new(std::env::current_exe().unwrap());
+    command
+        .args(["test_socket_activation", "--exact", "--test-threads=1"])
+        .env("LISTEN_POLL_ACTIVATION_CHILD", "1")
+        .env("LISTEN_POLL_TCP", tcp.local_addr().unwrap().to_string())
+        .env_remove("LISTEN_FDNAMES");
+    unsafe {
+        command.pre_exec(move || {
+            for (target, source) in sources.iter().enumerate() {
+                if libc::dup2(*source, 3 + target as libc::c_int) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+
+            Ok(())
+        });
+    }
+
+    let output = command.output().unwrap();
+    for fd in sources {
+        unsafe { libc::close(fd) };
+    }
+    std::fs::remove_file(&path).unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("1 passed"));
+}
+
+#[test]
+#[cfg(unix)]
+pub fn test_listener_handoff() {
+    use listener_poll::{confirm_listeners, receive_listeners, send_listeners, ActivatedListener};
+    use std::io::Write;
+
+    let tcp = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let tcp_addr = tcp.local_addr().unwrap();
+    let path = std::env::temp_dir().join(format!("listener_poll_handoff_{}.sock", std::process::id()));
+    _ = std::fs::remove_file(&path);
+    let unix = UnixListener::bind(&path).unwrap();
+    let old = [ActivatedListener::Tcp(tcp), ActivatedListener::Unix(unix)];
+
+    //Connections that are pending during the handoff are accepted by the new owner.
+    let _pending = TcpStream::connect(tcp_addr).unwrap();
+
+    let (old_stream, new_stream) = UnixStream::pair().unwrap();
+    let confirmed = Arc::new(AtomicBool::new(false));
+    let new_confirmed = Arc::clone(&confirmed);
+    let upgrade = thread::spawn(move || {
+        let mut listeners = receive_listeners(&new_stream).unwrap();
+        thread::sleep(Duration::from_millis(200));
+        new_confirmed.store(true, Ordering::SeqCst);
+        confirm_listeners(&new_stream).unwrap();
+
+        let (name, listener) = listeners.remove(0);
+        assert_eq!("web", name);
+        let ActivatedListener::Tcp(listener) = listener else {
+            panic!("web is not a tcp listener");
+        };
+        assert!(listener.accept_timeout(Some(Duration::from_secs(2))).unwrap().is_some());
+
+        let (name, listener) = listeners.remove(0);
+        assert_eq!("admin ✓", name);
+        assert!(matches!(listener, ActivatedListener::Unix(_)));
+    });
+
+    let listeners = [("web", &old[0]), ("admin ✓", &old[1])];
+    send_listeners(&old_stream, &listeners, Some(Duration::from_secs(5))).unwrap();
+    assert!(confirmed.load(Ordering::SeqCst));
+    drop(old);
+    upgrade.join().unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    //The receiver never confirms.
+    let tcp = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let (old_stream, new_stream) = UnixStream::pair().unwrap();
+    let time = Instant::now();
+    let error = send_listeners(&old_stream, &[("web", &tcp)], Some(Duration::from_millis(100))).unwrap_err();
+    assert_eq!(std::io::ErrorKind::TimedOut, error.kind());
+    assert!(time.elapsed().as_millis() >= 90);
+    assert_eq!(1, receive_listeners(&new_stream).unwrap().len());
+
+    //Only listening stream sockets are accepted.
+    let udp = std::net::UdpSocket::bind(("127.0.0.1", 0)).unwrap();
+    let (old_stream, new_stream) = UnixStream::pair().unwrap();
+    let error = send_listeners(&old_stream, &[("udp", &FdPoller::new(&udp))], Some(Duration::ZERO)).unwrap_err();
+    assert_eq!(std::io::ErrorKind::TimedOut, error.kind());
+    assert_eq!(std::io::ErrorKind::InvalidData, receive_listeners(&new_stream).unwrap_err().kind());
+
+    //Names are limited to 255 bytes.
+    let (old_stream, _new_stream) = UnixStream::pair().unwrap();
+    let name = "x".repeat(256);
+    let error = send_listeners(&old_stream, &[(name.as_str(), &tcp)], Some(Duration::ZERO)).unwrap_err();
+    assert_eq!(std::io::ErrorKind::InvalidInput, error.kind());
+
+    //A forged header with a huge length of the names is rejected before anything is allocated.
+    let (mut old_stream, new_stream) = UnixStream::pair().unwrap();
+    old_stream.write_all(b"LPH1\0\0\0\0\xff\xff\xff\xff").unwrap();
+    assert_eq!(std::io::ErrorKind::InvalidData, receive_listeners(&new_stream).unwrap_err().kind());
+
+    //The sender closes the stream.
+    let (old_stream, new_stream) = UnixStream::pair().unwrap();
+    drop(old_stream);
+    assert_eq!(std::io::ErrorKind::UnexpectedEof, receive_listeners(&new_stream).unwrap_err().kind());
+}
+
+#[test]
+#[cfg(unix)]
+pub fn test_sharded_listener() {
+    use listener_poll::ShardedListener;
+    use std::sync::atomic::AtomicUsize;
+
+    assert_eq!(std::io::ErrorKind::InvalidInput, ShardedListener::bind(([127, 0, 0, 1], 0).into(), 0).unwrap_err().kind());
+
+    let sharded = ShardedListener::bind(([127, 0, 0, 1], 0).into(), 4).unwrap();
+    let addr = sharded.local_addr().unwrap();
+    assert_ne!(0, addr.port());
+    assert_eq!(4, sharded.shards().len());
+    for shard in sharded.shards() {
+        assert_eq!(addr, shard.local_addr().unwrap());
+    }
+
+    let _streams = (0..40).map(|_| TcpStream::connect(addr).unwrap()).collect::<Vec<_>>();
+    let mut per_shard = [0; 4];
+    let mut set = ListenerSet::new();
+    for shard in sharded.shards() {
+        set.add(shard).unwrap();
+    }
+
+    while per_shard.iter().sum::<usize>() < 40 {
+        for token in set.poll(Some(Duration::from_secs(2))).unwrap() {
+            while sharded.shards()[token].accept_timeout(Some(Duration::ZERO)).unwrap().is_some() {
+                per_shard[token] += 1;
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        //Linux hashes the connections over the shards.
+        assert!(per_shard.iter().filter(|count| **count != 0).count() > 1, "{per_shard:?}");
+        sharded.attach_cpu_affinity().unwrap();
+    }
+
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&accepted);
+    let threads = sharded
+        .spawn(move |_, listener| {
+            while counter.load(Ordering::SeqCst) < 20 {
+                if listener.accept_timeout(Some(Duration::from_millis(50)))?.is_some() {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+
+            Ok(())
+        })
+        .unwrap();
+
+    let _streams = (0..20).map(|_| TcpStream::connect(addr).unwrap()).collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap().unwrap();
+    }
+    assert_eq!(20, accepted.load(Ordering::SeqCst));
+}
+
+#[cfg(unix)]
+#[test]
+pub fn test_listener_builder() {
+    use listener_poll::ListenerBuilder;
+    use std::os::fd::AsRawFd;
+
+    fn option(fd: &impl AsRawFd, level: libc::c_int, option: libc::c_int) -> libc::c_int {
+        let mut value: libc::c_int = 0;
+        let mut length = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+        assert_eq!(0, unsafe { libc::getsockopt(fd.as_raw_fd(), level, option, std::ptr::addr_of_mut!(value).cast(), &mut length) });
+        value
+    }
+
+    let builder = ListenerBuilder::new().backlog(16).reuse_address(true).only_v6(true);
+    assert_eq!(16, builder.get_backlog());
+    assert_eq!(Some(true), builder.get_only_v6());
+    assert!(builder.get_cloexec());
+    assert_eq!(ListenerBuilder::default(), ListenerBuilder::new());
+
+    let listener = builder.bind_tcp(([127, 0, 0, 1], 0).into()).unwrap();
+    assert_ne!(0, option(&listener, libc::SOL_SOCKET, libc::SO_REUSEADDR));
+    assert_eq!(0, option(&listener, libc::SOL_SOCKET, libc::SO_REUSEPORT));
+    assert_ne!(0, unsafe { libc::fcntl(listener.as_raw_fd(), libc::F_GETFD) } & libc::FD_CLOEXEC);
+    assert_eq!(false, listener.poll(Some(Duration::from_millis(10))).unwrap());
+    let _stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    assert!(listener.accept_timeout(Some(Duration::from_secs(2))).unwrap().is_some());
+
+    let inherit = ListenerBuilder::new().cloexec(false).bind_tcp(([127, 0, 0, 1], 0).into()).unwrap();
+    assert_eq!(0, unsafe { libc::fcntl(inherit.as_raw_fd(), libc::F_GETFD) } & libc::FD_CLOEXEC);
+
+    //Linux always reports IPV6_V6ONLY for specific addresses, so the unspecified address is used.
+    if let Ok(listener) = builder.bind_tcp("[::]:0".parse().unwrap()) {
+        assert_ne!(0, option(&listener, libc::IPPROTO_IPV6, libc::IPV6_V6ONLY));
+        let listener = builder.only_v6(false).bind_tcp("[::]:0".parse().unwrap()).unwrap();
+        assert_eq!(0, option(&listener, libc::IPPROTO_IPV6, libc::IPV6_V6ONLY));
+        let _stream = TcpStream::connect(("127.0.0.1", listener.local_addr().unwrap().port())).unwrap();
+        assert!(listener.accept_timeout(Some(Duration::from_secs(2))).unwrap().is_some());
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        let listener = ListenerBuilder::new()
+            .defer_accept(Some(Duration::from_secs(5)))
+            .fastopen(Some(64))
+            .bind_tcp(([127, 0, 0, 1], 0).into())
+            .unwrap();
+        assert_ne!(0, option(&listener, libc::IPPROTO_TCP, libc::TCP_DEFER_ACCEPT));
+        assert_eq!(64, option(&listener, libc::IPPROTO_TCP, libc::TCP_FASTOPEN));
+    }
+
+    let path = std::env::temp_dir().join(format!("listener_poll_builder_{}.sock", std::process::id()));
+    _ = std::fs::remove_file(&path);
+    let listener = ListenerBuilder::new().backlog(4).bind_unix(&path).unwrap();
+    assert_eq!(false, listener.poll(Some(Duration::from_millis(10))).unwrap());
+    let _stream = UnixStream::connect(&path).unwrap();
+    assert!(listener.accept_timeout(Some(Duration::from_secs(2))).unwrap().is_some());
+    std::fs::remove_file(&path).unwrap();
+
+    let long = std::env::temp_dir().join("x".repeat(200));
+    assert_eq!(std::io::ErrorKind::InvalidInput, ListenerBuilder::new().bind_unix(long).unwrap_err().kind());
+}
+
+#[cfg(unix)]
+#[test]
+pub fn test_unix_listener_builder() {
+    use listener_poll::UnixListenerBuilder;
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = std::env::temp_dir().join(format!("listener_poll_unix_builder_{}.sock", std::process::id()));
+    _ = std::fs::remove_file(&path);
+
+    let builder = UnixListenerBuilder::new().mode(0o600).remove_stale(true);
+    assert_eq!(Some(0o600), builder.get_mode());
+    assert!(builder.get_remove_on_drop());
+    let listener = builder.bind(&path).unwrap();
+    assert_eq!(Some(path.as_path()), listener.path());
+    assert_eq!(0o600, std::fs::metadata(&path).unwrap().permissions().mode() & 0o777);
+
+    //A listening socket is never removed.
+    assert_eq!(std::io::ErrorKind::AddrInUse, builder.bind(&path).unwrap_err().kind());
+    let _stream = UnixStream::connect(&path).unwrap();
+    assert!(listener.accept_timeout(Some(Duration::from_secs(2))).unwrap().is_some());
+    drop(listener);
+    assert!(!path.exists());
+
+    //A stale socket is only removed with remove_stale.
+    drop(UnixListener::bind(&path).unwrap());
+    assert_eq!(std::io::ErrorKind::AddrInUse, UnixListenerBuilder::new().bind(&path).unwrap_err().kind());
+    let listener = builder.bind(&path).unwrap();
+    assert_eq!(false, listener.poll(Some(Duration::from_millis(10))).unwrap());
+
+    //A socket that replaced the file is not removed on drop.
+    std::fs::remove_file(&path).unwrap();
+    let other = UnixListener::bind(&path).unwrap();
+    drop(listener);
+    assert!(path.exists());
+    drop(other);
+    std::fs::remove_file(&path).unwrap();
+
+    //The group is set before listening, the own group needs no privileges.
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let gid = unsafe { libc::getgid() };
+        let builder = UnixListenerBuilder::new().owner(None, Some(gid));
+        assert_eq!((None, Some(gid)), builder.get_owner());
+        let listener = builder.bind(&path).unwrap();
+        assert_eq!(gid, std::fs::symlink_metadata(&path).unwrap().gid());
+        assert_eq!(unsafe { libc::getuid() }, std::fs::symlink_metadata(&path).unwrap().uid());
+        drop(listener);
+        assert!(!path.exists());
+    }
+
+    //Other files are never removed.
+    std::fs::write(&path, b"data").unwrap();
+    assert_eq!(std::io::ErrorKind::AddrInUse, builder.bind(&path).unwrap_err().kind());
+    std::fs::remove_file(&path).unwrap();
+
+    let listener = UnixListenerBuilder::new().remove_on_drop(false).bind(&path).unwrap();
+    assert_eq!(None, listener.path());
+    let listener = listener.into_inner();
+    drop(listener);
+    assert!(path.exists());
+    std::fs::remove_file(&path).unwrap();
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        use std::os::linux::net::SocketAddrExt;
+
+        let name = format!("listener_poll_abstract_{}", std::process::id());
+        let listener = UnixListenerBuilder::new().bind_abstract(name.as_bytes()).unwrap();
+        assert_eq!(Some(name.as_bytes()), listener.local_addr().unwrap().as_abstract_name());
+        let addr = std::os::unix::net::SocketAddr::from_abstract_name(name.as_bytes()).unwrap();
+        let _stream = UnixStream::connect_addr(&addr).unwrap();
+        assert!(listener.accept_timeout(Some(Duration::from_secs(2))).unwrap().is_some());
+        assert_eq!(std::io::ErrorKind::InvalidInput, builder.bind_abstract(name.as_bytes()).unwrap_err().kind());
+    }
+}